@@ -0,0 +1,67 @@
+//! Benchmarks of the line diff on large outputs, run with `cargo bench`.
+use std::time::Instant;
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/diff.rs"]
+mod diff;
+
+/// Generates a log of `len` lines, where one line every `period` is changed
+/// if `edited` is true.
+fn generate_log(len: usize, period: usize, edited: bool) -> Vec<u8> {
+    let mut log = Vec::new();
+    for idx in 0..len {
+        if edited && idx % period == period / 2 {
+            log.extend_from_slice(format!("[warn] line {} has been edited\n", idx).as_bytes());
+        } else {
+            log.extend_from_slice(format!("[info] line {} {{\n}}\n", idx).as_bytes());
+        }
+    }
+    log
+}
+
+/// Runs the diff `iterations` times and prints the average duration.
+fn bench(name: &str, algorithm: diff::Algorithm, old: &[u8], new: &[u8], iterations: u32) {
+    let old_lines: Vec<&[u8]> = old.split(|byte| *byte == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new.split(|byte| *byte == b'\n').collect();
+    let start = Instant::now();
+    let mut edits = 0;
+    for _ in 0..iterations {
+        edits = diff::get_diff_with(algorithm, &old_lines, &new_lines)
+            .iter()
+            .filter(|line| !matches!(line, diff::DiffLine::Keep(_)))
+            .count();
+    }
+    let elapsed = start.elapsed() / iterations;
+    println!(
+        "{:<10} {:<28} {:>8} lines {:>6} edits {:>12.3?}",
+        algorithm.to_string(),
+        name,
+        old_lines.len(),
+        edits,
+        elapsed
+    );
+}
+
+fn main() {
+    let algorithms = [diff::Algorithm::Myers, diff::Algorithm::Patience, diff::Algorithm::Histogram];
+    for &algorithm in &algorithms {
+        let identical = generate_log(100_000, 1, false);
+        bench("identical", algorithm, &identical, &identical, 10);
+
+        for &(len, period) in &[(10_000, 1_000), (100_000, 1_000), (100_000, 100), (20_000, 10)] {
+            let old = generate_log(len, period, false);
+            let new = generate_log(len, period, true);
+            bench(&format!("one edit every {} lines", period), algorithm, &old, &new, 3);
+        }
+
+        // Only the first lines differ, the common suffix is trimmed
+        let old = generate_log(100_000, 1, false);
+        let mut new = b"[warn] starting\n".to_vec();
+        new.extend_from_slice(&old);
+        bench("edit at the top", algorithm, &old, &new, 10);
+
+        let old = generate_log(5_000, 1, false);
+        let new = generate_log(5_000, 1, true);
+        bench("all lines edited", algorithm, &old, &new, 1);
+    }
+}
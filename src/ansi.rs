@@ -0,0 +1,369 @@
+use std::borrow::Cow;
+use std::io;
+use std::io::Write;
+use unicode_width::UnicodeWidthChar;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// A piece of a byte string, either text or an escape sequence.
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Text(u8),
+    /// A Select Graphic Rendition sequence (`ESC [ ... m`), holds the parameters.
+    Sgr(&'a [u8]),
+    /// Any other escape sequence.
+    Escape(&'a [u8]),
+}
+
+/// The graphic rendition state of the terminal.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Style {
+    attributes: [bool; 10],
+    fg: Option<Vec<u16>>,
+    bg: Option<Vec<u16>>,
+}
+
+/// Returns true if the body contains any escape sequence.
+pub fn has_escapes(body: &[u8]) -> bool {
+    body.contains(&ESC)
+}
+
+/// Removes all escape sequences.
+pub fn strip(body: &[u8]) -> Cow<'_, [u8]> {
+    if !has_escapes(body) {
+        return Cow::Borrowed(body);
+    }
+    let mut stripped = Vec::with_capacity(body.len());
+    for token in tokenize(body) {
+        if let Token::Text(byte) = token {
+            stripped.push(byte);
+        }
+    }
+    Cow::Owned(stripped)
+}
+
+/// Rewrites the graphic rendition sequences in a canonical form, so that two
+/// bodies rendering the same text with the same styles are equal.
+/// Each line is self-contained: it starts with the default style and ends by
+/// resetting the style if needed.
+pub fn normalize(body: &[u8]) -> Cow<'_, [u8]> {
+    if !has_escapes(body) {
+        return Cow::Borrowed(body);
+    }
+    let default = Style::default();
+    let mut current = Style::default();
+    let mut emitted = Style::default();
+    let mut normalized = Vec::with_capacity(body.len());
+    for token in tokenize(body) {
+        match token {
+            Token::Sgr(params) => current.apply(params),
+            Token::Escape(sequence) => normalized.extend_from_slice(sequence),
+            Token::Text(b'\n') => {
+                if emitted != default {
+                    normalized.extend_from_slice(b"\x1b[0m");
+                    emitted = Style::default();
+                }
+                normalized.push(b'\n');
+            }
+            Token::Text(byte) => {
+                if current != emitted {
+                    normalized.extend_from_slice(current.to_sgr().as_bytes());
+                    emitted = current.clone();
+                }
+                normalized.push(byte);
+            }
+        }
+    }
+    if emitted != default {
+        normalized.extend_from_slice(b"\x1b[0m");
+    }
+    Cow::Owned(normalized)
+}
+
+/// Makes escape sequences visible, for instance the red foreground sequence
+/// is written `\e[31m`.
+pub fn make_visible(body: &[u8]) -> Vec<u8> {
+    let mut visible = Vec::with_capacity(body.len());
+    for byte in body {
+        if *byte == ESC {
+            visible.extend_from_slice(b"\\e");
+        } else {
+            visible.push(*byte);
+        }
+    }
+    visible
+}
+
+/// Truncates a line to at most `width` columns. Graphic rendition sequences
+/// are kept but take no column, the style is reset at the end of lines
+/// containing some. Other sequences are removed as they may move the cursor.
+pub fn truncate(line: &str, width: usize) -> String {
+    let mut kept = Vec::with_capacity(line.len());
+    for token in tokenize(line.as_bytes()) {
+        match token {
+            Token::Text(byte) => kept.push(byte),
+            Token::Sgr(params) => {
+                kept.extend_from_slice(b"\x1b[");
+                kept.extend_from_slice(params);
+                kept.push(b'm');
+            }
+            Token::Escape(_) => (),
+        }
+    }
+    let line = String::from_utf8_lossy(&kept);
+    let mut truncated = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    let mut columns = 0;
+    let mut has_escapes = false;
+    while let Some(c) = chars.next() {
+        if c == ESC as char {
+            has_escapes = true;
+            truncated.push(c);
+            match chars.next() {
+                Some('[') => {
+                    truncated.push('[');
+                    for c in &mut chars {
+                        truncated.push(c);
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                Some(c) => truncated.push(c),
+                None => (),
+            }
+            continue;
+        }
+        columns += c.width().unwrap_or(0);
+        if columns > width {
+            break;
+        }
+        truncated.push(c);
+    }
+    if has_escapes {
+        truncated.push_str("\x1b[0m");
+    }
+    truncated
+}
+
+/// A writer removing escape sequences and carriage returns, which are only
+/// meaningful on terminals. Lines are written once complete, or when the
+/// writer is dropped.
+pub struct StripWriter<W: Write> {
+    inner: W,
+    /// The incomplete line, which may end in the middle of a sequence
+    line: Vec<u8>,
+}
+
+impl<W: Write> StripWriter<W> {
+    pub fn new(inner: W) -> StripWriter<W> {
+        StripWriter {
+            inner,
+            line: Vec::new(),
+        }
+    }
+
+    /// Writes the given lines without escape sequences nor carriage returns.
+    fn write_stripped(&mut self, lines: &[u8]) -> io::Result<()> {
+        let lines: Vec<u8> = strip(lines).iter().filter(|byte| **byte != b'\r').copied().collect();
+        self.inner.write_all(&lines)
+    }
+}
+
+impl<W: Write> Write for StripWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if let Some(end) = self.line.iter().rposition(|byte| *byte == b'\n') {
+            let lines: Vec<u8> = self.line.drain(..=end).collect();
+            self.write_stripped(&lines)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for StripWriter<W> {
+    fn drop(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        self.write_stripped(&line).ok();
+        self.inner.flush().ok();
+    }
+}
+
+/// Splits a body into text bytes and escape sequences.
+fn tokenize(body: &[u8]) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+    while idx < body.len() {
+        if body[idx] != ESC {
+            tokens.push(Token::Text(body[idx]));
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        match body.get(idx + 1) {
+            // Control Sequence Introducer, ends with a byte in 0x40..=0x7e
+            Some(b'[') => {
+                idx += 2;
+                while idx < body.len() && !(0x40..=0x7e).contains(&body[idx]) {
+                    idx += 1;
+                }
+                let end = std::cmp::min(idx + 1, body.len());
+                if body.get(idx) == Some(&b'm') {
+                    tokens.push(Token::Sgr(&body[start + 2..idx]));
+                } else {
+                    tokens.push(Token::Escape(&body[start..end]));
+                }
+                idx = end;
+            }
+            // Operating System Command, ends with BEL or ESC '\'
+            Some(b']') => {
+                idx += 2;
+                while idx < body.len() && body[idx] != BEL && !(body[idx] == ESC && body.get(idx + 1) == Some(&b'\\')) {
+                    idx += 1;
+                }
+                let end = if body.get(idx) == Some(&ESC) { idx + 2 } else { idx + 1 };
+                let end = std::cmp::min(end, body.len());
+                tokens.push(Token::Escape(&body[start..end]));
+                idx = end;
+            }
+            // Character set designation, such as ESC '(' 'B'
+            Some(b'(') | Some(b')') | Some(b'*') | Some(b'+') => {
+                let end = std::cmp::min(idx + 3, body.len());
+                tokens.push(Token::Escape(&body[start..end]));
+                idx = end;
+            }
+            Some(_) => {
+                tokens.push(Token::Escape(&body[start..idx + 2]));
+                idx += 2;
+            }
+            None => {
+                tokens.push(Token::Escape(&body[start..]));
+                idx += 1;
+            }
+        }
+    }
+    tokens
+}
+
+impl Style {
+    /// Updates the style with the parameters of a graphic rendition sequence.
+    fn apply(&mut self, params: &[u8]) {
+        let params: Vec<u16> = String::from_utf8_lossy(params)
+            .split([';', ':'])
+            .map(|param| param.parse().unwrap_or(0))
+            .collect();
+        let mut idx = 0;
+        while idx < params.len() {
+            let param = params[idx];
+            match param {
+                0 => *self = Style::default(),
+                1..=9 => self.attributes[param as usize] = true,
+                21 | 22 => {
+                    self.attributes[1] = false;
+                    self.attributes[2] = false;
+                }
+                23..=29 => {
+                    self.attributes[param as usize - 20] = false;
+                    if param == 25 {
+                        self.attributes[6] = false;
+                    }
+                }
+                30..=37 | 90..=97 => self.fg = Some(vec![param]),
+                40..=47 | 100..=107 => self.bg = Some(vec![param]),
+                39 => self.fg = None,
+                49 => self.bg = None,
+                38 | 48 => {
+                    let len = match params.get(idx + 1) {
+                        Some(5) => 3,
+                        Some(2) => 5,
+                        _ => 1,
+                    };
+                    let end = std::cmp::min(idx + len, params.len());
+                    let color = Some(params[idx..end].to_vec());
+                    if param == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                    idx = end - 1;
+                }
+                _ => (),
+            }
+            idx += 1;
+        }
+    }
+
+    /// Returns the canonical graphic rendition sequence of the style.
+    fn to_sgr(&self) -> String {
+        let mut params = vec![String::from("0")];
+        for (attribute, enabled) in self.attributes.iter().enumerate() {
+            if *enabled {
+                params.push(attribute.to_string());
+            }
+        }
+        for color in self.fg.iter().chain(self.bg.iter()) {
+            let color: Vec<String> = color.iter().map(|param| param.to_string()).collect();
+            params.push(color.join(";"));
+        }
+        format!("\x1b[{}m", params.join(";"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(strip(b"plain"), &b"plain"[..]);
+        assert_eq!(strip(b"\x1b[1;31mred\x1b[0m text"), &b"red text"[..]);
+        assert_eq!(strip(b"\x1b]0;title\x07a\x1b[2Kb\x1b(Bc"), &b"abc"[..]);
+    }
+
+    #[test]
+    fn test_strip_writer() {
+        let mut output = Vec::new();
+        {
+            let mut writer = StripWriter::new(&mut output);
+            writer.write_all(b"\x1b[3").unwrap();
+            writer.write_all(b"1mred\x1b[0m\r\nline\r").unwrap();
+            writer.write_all(b"\n\rlast\x1b[0m").unwrap();
+        }
+        assert_eq!(output, b"red\nline\nlast");
+    }
+
+    #[test]
+    fn test_normalize() {
+        // Equivalent styles
+        assert_eq!(normalize(b"\x1b[1;31mred\x1b[0m"), normalize(b"\x1b[31m\x1b[1mred\x1b[m"));
+        assert_eq!(normalize(b"\x1b[31m\x1b[0mplain"), normalize(b"plain"));
+        assert_eq!(normalize(b"\x1b[32mgreen"), normalize(b"\x1b[32mgreen\x1b[39m"));
+
+        // Different styles
+        assert_ne!(normalize(b"\x1b[31mred"), normalize(b"\x1b[32mred"));
+        assert_ne!(normalize(b"\x1b[38;5;1mred"), normalize(b"\x1b[38;5;2mred"));
+
+        // Lines are self-contained
+        assert_eq!(normalize(b"\x1b[31ma\nb"), &b"\x1b[0;31ma\x1b[0m\n\x1b[0;31mb\x1b[0m"[..]);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("abcdef", 4), "abcd");
+        assert_eq!(truncate("ab", 4), "ab");
+        assert_eq!(truncate("\x1b[31mred\x1b[0m text", 5), "\x1b[31mred\x1b[0m t\x1b[0m");
+        assert_eq!(truncate("日本語", 5), "日本");
+        assert_eq!(truncate("\x1b[2Jab\x1b[1;1Hc\x1b]0;title\x07d", 3), "abc");
+        assert_eq!(truncate("\x1b[1ma\x1b[Kb", 4), "\x1b[1mab\x1b[0m");
+    }
+
+    #[test]
+    fn test_make_visible() {
+        assert_eq!(make_visible(b"\x1b[31mred"), b"\\e[31mred".to_vec());
+    }
+}
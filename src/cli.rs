@@ -1,8 +1,11 @@
 extern crate clap;
 
-use clap::Clap;
+use clap::{AppSettings, Clap};
 use std::path::PathBuf;
 
+use crate::diff::{Algorithm, Layout};
+use crate::parser::TagChange;
+
 #[derive(Clap)]
 #[clap(version = "0.0.3")]
 #[clap(verbatim_doc_comment)]
@@ -20,6 +23,18 @@ pub struct Config {
     #[clap(short, long, default_value = ".", parse(from_os_str))]
     pub path: PathBuf,
 
+    /// Number of unchanged lines displayed around changes in diffs
+    #[clap(short = "C", long, default_value = "3")]
+    pub context: usize,
+
+    /// Diff algorithm: myers, patience or histogram
+    #[clap(long)]
+    pub diff: Option<Algorithm>,
+
+    /// Diff layout: auto (side by side on wide terminals), unified or split
+    #[clap(long)]
+    pub layout: Option<Layout>,
+
     /// Verbode mode
     #[clap(short, long)]
     pub verbose: bool,
@@ -36,11 +51,42 @@ pub enum Command {
         #[clap(short, long)]
         name: Option<String>,
 
+        /// Expected exit code: exact, any, nonzero, a set (0,2) or a range (1-3)
+        #[clap(short, long)]
+        code: Option<String>,
+
+        /// How stdout is compared: exact, ignore, lines, json, 'contains <text>' or 'regex <pattern>'
+        #[clap(long)]
+        stdout: Option<String>,
+
+        /// How stderr is compared: exact, ignore, lines, json, 'contains <text>' or 'regex <pattern>'
+        #[clap(long)]
+        stderr: Option<String>,
+
+        /// How escape sequences are compared: keep, strip or semantic
+        #[clap(long)]
+        ansi: Option<String>,
+
+        /// Which whitespace differences are ignored: exact, all or a list of trailing, eol, blank and collapse
+        #[clap(long)]
+        whitespace: Option<String>,
+
         /// Accept the snapshot
         #[clap(short, long)]
         yes: bool,
     },
 
+    /// Write the changes of snapshot outputs as a unified patch
+    Diff {
+        /// Only check the snapshot with this name
+        #[clap(short, long)]
+        name: Option<String>,
+
+        /// Write the patch to this file instead of stdout
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
     /// Execute a script
     Exec { cmd: String },
 
@@ -48,7 +94,57 @@ pub enum Command {
     Init {},
 
     /// Run snapshot tests
-    Run {},
+    Run {
+        /// Run each snapshot N times to detect flaky snapshots
+        #[clap(short, long, default_value = "1")]
+        repeat: usize,
+
+        /// Tag flaky snapshots with #flaky, requires --repeat
+        #[clap(long)]
+        tag_flaky: bool,
+    },
+
+    /// Change the settings of snapshots
+    Set {
+        /// Only change the snapshot with this name, instead of all snapshots
+        #[clap(short, long)]
+        name: Option<String>,
+
+        /// Expected exit code: exact, any, nonzero, a set (0,2) or a range (1-3)
+        #[clap(short, long)]
+        code: Option<String>,
+
+        /// How stdout is compared: exact, ignore, lines, json, 'contains <text>' or 'regex <pattern>'
+        #[clap(long)]
+        stdout: Option<String>,
+
+        /// How stderr is compared: exact, ignore, lines, json, 'contains <text>' or 'regex <pattern>'
+        #[clap(long)]
+        stderr: Option<String>,
+
+        /// How escape sequences are compared: keep, strip or semantic
+        #[clap(long)]
+        ansi: Option<String>,
+
+        /// Which whitespace differences are ignored: exact, all or a list of trailing, eol, blank and collapse
+        #[clap(long)]
+        whitespace: Option<String>,
+    },
+
+    /// Add (+tag) or remove (-tag) tags of snapshots
+    #[clap(setting = AppSettings::AllowLeadingHyphen)]
+    Tag {
+        /// Tag changes, such as +slow or -flaky
+        #[clap(required = true)]
+        changes: Vec<TagChange>,
+
+        /// Only change the snapshot with this name, instead of all snapshots
+        #[clap(long)]
+        name: Option<String>,
+    },
+
+    /// Open the full-screen interface
+    Tui {},
 }
 
 /// Parse CLI args, may terminate the program
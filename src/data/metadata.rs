@@ -4,7 +4,7 @@ use std::fs;
 use std::path::PathBuf;
 use std::rc::Rc;
 
-use super::Snapshot;
+use super::{AnsiMode, ExpectedCode, Snapshot, StreamMode, Whitespace};
 use crate::error::{wrap, Error};
 
 #[derive(Serialize, Deserialize)]
@@ -19,6 +19,20 @@ pub struct Metadata {
     pub description: Option<String>,
     pub tags: Vec<String>,
     pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub expected_code: ExpectedCode,
+    #[serde(default)]
+    pub stdout_mode: StreamMode,
+    #[serde(default)]
+    pub stderr_mode: StreamMode,
+    #[serde(default)]
+    pub ansi: AnsiMode,
+    #[serde(default)]
+    pub whitespace: Whitespace,
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(default)]
+    pub core_dumped: bool,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
 }
@@ -66,6 +80,13 @@ impl MetadataManager {
                 description: snap.description.clone(),
                 tags: snap.tags.clone(),
                 exit_code: snap.exit_code.clone(),
+                expected_code: snap.expected_code.clone(),
+                stdout_mode: snap.stdout_mode.clone(),
+                stderr_mode: snap.stderr_mode.clone(),
+                ansi: snap.ansi,
+                whitespace: snap.whitespace,
+                signal: snap.signal,
+                core_dumped: snap.core_dumped,
                 stdout,
                 stderr,
             })
@@ -1,9 +1,16 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
 
+use crate::ansi;
+use crate::diff::{Algorithm, Layout};
 use crate::error::{wrap, Error};
+use crate::json;
 
 mod metadata;
 mod snapshots;
@@ -11,16 +18,105 @@ mod snapshots;
 pub const PARROT_PATH: &'static str = ".parrot";
 const SNAPSHOT_PATH: &'static str = "snapshots";
 const METADATA_PATH: &'static str = "metadata.json";
+const CONFIG_PATH: &str = "config.json";
+const HISTORY_PATH: &str = "history";
 
-#[derive(PartialEq, Eq)]
+/// The maximum number of commands kept in the REPL history.
+const HISTORY_SIZE: usize = 1000;
+
+/// The project settings, optionally written by users in `.parrot/config.json`.
+#[derive(Deserialize, Default)]
+pub struct ProjectConfig {
+    /// The diff algorithm used unless another one is given on the command line.
+    #[serde(default)]
+    pub diff: Algorithm,
+    /// The diff layout used unless another one is given on the command line.
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub enum SnapshotStatus {
     Failed,
     Passed,
     Waiting,
+    Flaky,
+}
+
+/// What is expected from the exit code of a snapshot.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub enum ExpectedCode {
+    /// The exit code must be the one recorded in the snapshot.
+    #[default]
+    Exact,
+    /// The exit code must be one of the set.
+    Set(Vec<i32>),
+    /// The exit code must be within the (inclusive) range.
+    Range(i32, i32),
+    /// The exit code must not be zero.
+    NonZero,
+    /// The exit code is not checked.
+    Ignore,
+}
+
+/// How the output of a stream is compared against the snapshot.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub enum StreamMode {
+    /// The output must be the same as the snapshot.
+    #[default]
+    Exact,
+    /// The output is not checked.
+    Ignore,
+    /// The output must contain the text.
+    Contains(String),
+    /// The output must match the regular expression.
+    Regex(CompiledRegex),
+    /// The output must have the same lines as the snapshot, in any order.
+    Lines,
+    /// The output must be structurally equal to the snapshot as JSON, values
+    /// at ignored paths are skipped and numbers may differ up to tolerance.
+    Json { ignore: Vec<String>, tolerance: f64 },
+}
+
+/// A regular expression compiled once, stored as its pattern.
+#[derive(Debug, Clone)]
+pub struct CompiledRegex(regex::bytes::Regex);
+
+/// How escape sequences in the outputs are handled before comparison.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum AnsiMode {
+    /// Escape sequences are compared byte for byte.
+    #[default]
+    Keep,
+    /// Escape sequences are removed.
+    Strip,
+    /// Outputs must render the same text with the same styles.
+    Semantic,
+}
+
+/// Which whitespace differences are tolerated when comparing outputs.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Whitespace {
+    /// Trailing spaces and tabs are ignored.
+    pub trailing: bool,
+    /// `\r\n` line endings are equivalent to `\n`.
+    pub line_endings: bool,
+    /// Blank lines are ignored.
+    pub blank_lines: bool,
+    /// Runs of spaces and tabs are equivalent to a single space.
+    pub collapse: bool,
 }
 
 pub struct Snapshot {
     pub exit_code: Option<i32>,
+    pub expected_code: ExpectedCode,
+    pub stdout_mode: StreamMode,
+    pub stderr_mode: StreamMode,
+    pub ansi: AnsiMode,
+    pub whitespace: Whitespace,
+    /// The signal that terminated the process, if any.
+    pub signal: Option<i32>,
+    pub core_dumped: bool,
     pub stderr: Option<SnapshotData>,
     pub stdout: Option<SnapshotData>,
     pub cmd: String,
@@ -28,6 +124,10 @@ pub struct Snapshot {
     pub description: Option<String>,
     pub tags: Vec<String>,
     pub status: SnapshotStatus,
+    /// The differences found by the latest run, if it failed.
+    pub diff: Option<Vec<u8>>,
+    /// Marked snapshots are the targets of commands passed '@'.
+    pub marked: bool,
     pub deleted: bool,
 }
 
@@ -70,6 +170,46 @@ impl DataManager {
         })
     }
 
+    /// Reads the project configuration, the default configuration is returned
+    /// if there is none.
+    pub fn get_config(&self) -> Result<ProjectConfig, Error> {
+        let path = self.path.join(CONFIG_PATH);
+        if !path.exists() {
+            return Ok(ProjectConfig::default());
+        }
+        let file = wrap(fs::File::open(&path), "Could not open config.json.")?;
+        wrap(serde_json::from_reader(file), "Failed to parse config.json.")
+    }
+
+    /// Reads the REPL history, from the oldest command to the most recent one.
+    /// The history file is truncated when it grows too large.
+    pub fn get_history(&self) -> Result<Vec<String>, Error> {
+        let path = self.path.join(HISTORY_PATH);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let history = wrap(fs::read_to_string(&path), "Could not read the history file.")?;
+        let mut history: Vec<String> = history.lines().map(String::from).collect();
+        history.dedup();
+        if history.len() > HISTORY_SIZE {
+            history.drain(..history.len() - HISTORY_SIZE);
+            let mut content = history.join("\n");
+            content.push('\n');
+            wrap(fs::write(&path, content), "Failed to write the history file.")?;
+        }
+        Ok(history)
+    }
+
+    /// Appends a command to the REPL history.
+    pub fn add_history(&self, command: &str) -> Result<(), Error> {
+        let path = self.path.join(HISTORY_PATH);
+        let mut file = wrap(
+            fs::OpenOptions::new().create(true).append(true).open(&path),
+            "Failed to open the history file.",
+        )?;
+        wrap(writeln!(file, "{}", command), "Failed to write the history file.")
+    }
+
     /// Initializes the Parrot storage folder.
     pub fn initialize(&mut self) -> Result<(), Error> {
         if self.path.exists() {
@@ -127,6 +267,12 @@ impl DataManager {
         Ok(())
     }
 
+    /// Returns the path of the file holding a snapshot body, such as
+    /// `.parrot/snapshots/hello.out.txt`.
+    pub fn get_snapshot_path(&self, name: &str) -> PathBuf {
+        self.snap_manager.get_path(name)
+    }
+
     /// Returns a vector of snapshot references.
     pub fn get_all_snapshots(&mut self) -> Result<Vec<Rc<RefCell<Snapshot>>>, Error> {
         let mut snaps = Vec::new();
@@ -156,6 +302,13 @@ impl DataManager {
             let stderr = self.load_snapshot_body(snap.stderr)?;
             snaps.push(Rc::new(RefCell::new(Snapshot {
                 exit_code: snap.exit_code,
+                expected_code: snap.expected_code,
+                stdout_mode: snap.stdout_mode,
+                stderr_mode: snap.stderr_mode,
+                ansi: snap.ansi,
+                whitespace: snap.whitespace,
+                signal: snap.signal,
+                core_dumped: snap.core_dumped,
                 stderr,
                 stdout,
                 cmd: snap.cmd,
@@ -163,6 +316,8 @@ impl DataManager {
                 description: snap.description,
                 tags: snap.tags,
                 status: SnapshotStatus::Waiting,
+                diff: None,
+                marked: false,
                 deleted: false,
             })))
         }
@@ -181,3 +336,522 @@ impl DataManager {
         }
     }
 }
+
+impl ExpectedCode {
+    /// Returns true if `code` satisfies the expectation, given the exit code
+    /// recorded in the snapshot.
+    pub fn matches(&self, recorded: Option<i32>, code: Option<i32>) -> bool {
+        match self {
+            ExpectedCode::Exact => recorded == code,
+            ExpectedCode::Set(codes) => matches!(code, Some(code) if codes.contains(&code)),
+            ExpectedCode::Range(min, max) => matches!(code, Some(code) if *min <= code && code <= *max),
+            ExpectedCode::NonZero => code != Some(0),
+            ExpectedCode::Ignore => true,
+        }
+    }
+}
+
+impl FromStr for ExpectedCode {
+    type Err = Error;
+
+    /// Parses an expectation, either `exact`, `any`, `nonzero`, a set such as
+    /// `0,2` or a range such as `1-3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let error = || Error {
+            message: format!(
+                "Invalid exit code expectation '{}', expected 'exact', 'any', 'nonzero', a set (0,2) or a range (1-3).",
+                s
+            ),
+            cause: None,
+        };
+        match s {
+            "exact" => return Ok(ExpectedCode::Exact),
+            "any" | "ignore" => return Ok(ExpectedCode::Ignore),
+            "nonzero" | "!0" => return Ok(ExpectedCode::NonZero),
+            _ => (),
+        }
+        // A leading '-' is a negative code rather than a range
+        if let Some(idx) = s.get(1..).and_then(|rest| rest.find('-')) {
+            let (min, max) = (&s[..idx + 1], &s[idx + 2..]);
+            return match (min.trim().parse(), max.trim().parse()) {
+                (Ok(min), Ok(max)) if min <= max => Ok(ExpectedCode::Range(min, max)),
+                _ => Err(error()),
+            };
+        }
+        let mut codes = Vec::new();
+        for code in s.split(',') {
+            codes.push(code.trim().parse().map_err(|_| error())?);
+        }
+        Ok(ExpectedCode::Set(codes))
+    }
+}
+
+impl std::fmt::Display for ExpectedCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExpectedCode::Exact => write!(f, "exact"),
+            ExpectedCode::Set(codes) => {
+                let codes: Vec<String> = codes.iter().map(|code| code.to_string()).collect();
+                write!(f, "{}", codes.join(","))
+            }
+            ExpectedCode::Range(min, max) => write!(f, "{}-{}", min, max),
+            ExpectedCode::NonZero => write!(f, "nonzero"),
+            ExpectedCode::Ignore => write!(f, "any"),
+        }
+    }
+}
+
+impl StreamMode {
+    /// Returns true if the `output` of a stream satisfies the mode, given the
+    /// body recorded in the snapshot.
+    pub fn matches(&self, recorded: &[u8], output: &[u8]) -> bool {
+        match self {
+            StreamMode::Exact => recorded == output,
+            StreamMode::Ignore => true,
+            StreamMode::Contains(text) => contains(output, text.as_bytes()),
+            StreamMode::Regex(regex) => regex.is_match(output),
+            StreamMode::Lines => sorted_lines(recorded) == sorted_lines(output),
+            StreamMode::Json { ignore, tolerance } => {
+                match (serde_json::from_slice(recorded), serde_json::from_slice(output)) {
+                    (Ok(old), Ok(new)) => json::get_json_diff(&old, &new, &json::parse_paths(ignore), *tolerance).is_empty(),
+                    _ => recorded == output,
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for StreamMode {
+    type Err = Error;
+
+    /// Parses a stream mode, either `exact`, `ignore`, `lines`, `contains <text>`,
+    /// `regex <pattern>` or `json [ignore=<path>,...] [tolerance=<number>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (mode, arg) = match s.find(char::is_whitespace) {
+            Some(idx) => (&s[..idx], s[idx..].trim()),
+            None => (s, ""),
+        };
+        match (mode, arg) {
+            ("exact", "") => Ok(StreamMode::Exact),
+            ("ignore", "") => Ok(StreamMode::Ignore),
+            ("lines", "") => Ok(StreamMode::Lines),
+            ("json", args) => parse_json_mode(args),
+            ("contains", text) if !text.is_empty() => Ok(StreamMode::Contains(text.to_owned())),
+            ("regex", pattern) if !pattern.is_empty() => Ok(StreamMode::Regex(pattern.parse()?)),
+            _ => Err(Error {
+                message: format!(
+                    "Invalid stream mode '{}', expected 'exact', 'ignore', 'lines', 'json', 'contains <text>' or 'regex <pattern>'.",
+                    s
+                ),
+                cause: None,
+            }),
+        }
+    }
+}
+
+impl CompiledRegex {
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.0.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl FromStr for CompiledRegex {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        match regex::bytes::Regex::new(pattern) {
+            Ok(regex) => Ok(CompiledRegex(regex)),
+            Err(err) => Err(Error {
+                message: format!("Invalid regex '{}'.", pattern),
+                cause: Some(format!("{}", err)),
+            }),
+        }
+    }
+}
+
+impl PartialEq for CompiledRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl std::fmt::Display for CompiledRegex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Regular expressions are stored as their pattern, which is checked when
+/// the metadata is loaded.
+impl Serialize for CompiledRegex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CompiledRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        pattern.parse().map_err(|err: Error| serde::de::Error::custom(err.message))
+    }
+}
+
+impl std::fmt::Display for StreamMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StreamMode::Exact => write!(f, "exact"),
+            StreamMode::Ignore => write!(f, "ignore"),
+            StreamMode::Contains(text) => write!(f, "contains {}", text),
+            StreamMode::Regex(pattern) => write!(f, "regex {}", pattern),
+            StreamMode::Lines => write!(f, "lines"),
+            StreamMode::Json { ignore, tolerance } => {
+                write!(f, "json")?;
+                if !ignore.is_empty() {
+                    write!(f, " ignore={}", ignore.join(","))?;
+                }
+                if *tolerance > 0.0 {
+                    write!(f, " tolerance={}", tolerance)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl AnsiMode {
+    /// Prepares an output for comparison.
+    pub fn apply<'a>(&self, body: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            AnsiMode::Keep => Cow::Borrowed(body),
+            AnsiMode::Strip => ansi::strip(body),
+            AnsiMode::Semantic => ansi::normalize(body),
+        }
+    }
+}
+
+impl FromStr for AnsiMode {
+    type Err = Error;
+
+    /// Parses an ANSI mode, either `keep`, `strip` or `semantic`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "keep" => Ok(AnsiMode::Keep),
+            "strip" => Ok(AnsiMode::Strip),
+            "semantic" => Ok(AnsiMode::Semantic),
+            s => Error::from_str(&format!(
+                "Invalid ANSI mode '{}', expected 'keep', 'strip' or 'semantic'.",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for AnsiMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AnsiMode::Keep => write!(f, "keep"),
+            AnsiMode::Strip => write!(f, "strip"),
+            AnsiMode::Semantic => write!(f, "semantic"),
+        }
+    }
+}
+
+impl Whitespace {
+    const OPTIONS: [&'static str; 4] = ["trailing", "eol", "blank", "collapse"];
+
+    /// Prepares an output for comparison.
+    pub fn apply<'a>(&self, body: &'a [u8]) -> Cow<'a, [u8]> {
+        if *self == Whitespace::default() {
+            return Cow::Borrowed(body);
+        }
+        let mut lines = Vec::new();
+        for line in body.split(|byte| *byte == b'\n') {
+            let mut line = line;
+            if self.line_endings {
+                line = line.strip_suffix(b"\r").unwrap_or(line);
+            }
+            if self.trailing {
+                while let Some((b' ', rest)) | Some((b'\t', rest)) = line.split_last() {
+                    line = rest;
+                }
+            }
+            if self.blank_lines && line.iter().all(|byte| byte.is_ascii_whitespace()) {
+                continue;
+            }
+            if self.collapse {
+                let mut collapsed = Vec::with_capacity(line.len());
+                for byte in line {
+                    let is_blank = *byte == b' ' || *byte == b'\t';
+                    if !is_blank {
+                        collapsed.push(*byte);
+                    } else if collapsed.last() != Some(&b' ') {
+                        collapsed.push(b' ');
+                    }
+                }
+                lines.push(Cow::Owned(collapsed));
+            } else {
+                lines.push(Cow::Borrowed(line));
+            }
+        }
+        Cow::Owned(lines.join(&b'\n'))
+    }
+
+    /// Returns the enabled options.
+    fn options(&self) -> [bool; 4] {
+        [self.trailing, self.line_endings, self.blank_lines, self.collapse]
+    }
+}
+
+impl FromStr for Whitespace {
+    type Err = Error;
+
+    /// Parses whitespace options, either `exact`, `all` or a comma separated
+    /// list of `trailing`, `eol`, `blank` and `collapse`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut whitespace = Whitespace::default();
+        for option in s.split(',').map(str::trim) {
+            match option {
+                "exact" => (),
+                "all" => {
+                    whitespace = Whitespace {
+                        trailing: true,
+                        line_endings: true,
+                        blank_lines: true,
+                        collapse: true,
+                    }
+                }
+                "trailing" => whitespace.trailing = true,
+                "eol" => whitespace.line_endings = true,
+                "blank" => whitespace.blank_lines = true,
+                "collapse" => whitespace.collapse = true,
+                _ => {
+                    return Error::from_str(&format!(
+                        "Invalid whitespace option '{}', expected 'exact', 'all' or a list of 'trailing', 'eol', 'blank' and 'collapse'.",
+                        option
+                    ))
+                }
+            }
+        }
+        Ok(whitespace)
+    }
+}
+
+impl std::fmt::Display for Whitespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let options: Vec<&str> = Whitespace::OPTIONS
+            .iter()
+            .zip(self.options().iter())
+            .filter(|(_, enabled)| **enabled)
+            .map(|(option, _)| *option)
+            .collect();
+        if options.is_empty() {
+            write!(f, "exact")
+        } else {
+            write!(f, "{}", options.join(","))
+        }
+    }
+}
+
+impl Snapshot {
+    /// Prepares an output for comparison according to the snapshot's ANSI
+    /// and whitespace settings.
+    pub fn prepare<'a>(&self, body: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.ansi.apply(body) {
+            Cow::Borrowed(body) => self.whitespace.apply(body),
+            Cow::Owned(body) => Cow::Owned(self.whitespace.apply(&body).into_owned()),
+        }
+    }
+}
+
+/// Parses the arguments of the json stream mode.
+fn parse_json_mode(args: &str) -> Result<StreamMode, Error> {
+    let mut ignore = Vec::new();
+    let mut tolerance = 0.0;
+    for arg in args.split_whitespace() {
+        if let Some(paths) = arg.strip_prefix("ignore=") {
+            for path in paths.split(',') {
+                if let Err(message) = json::parse_path(path) {
+                    return Err(Error { message, cause: None });
+                }
+                ignore.push(path.to_owned());
+            }
+        } else if let Some(value) = arg.strip_prefix("tolerance=") {
+            tolerance = match value.parse::<f64>() {
+                Ok(value) if value >= 0.0 => value,
+                _ => return Error::from_str(&format!("Invalid JSON tolerance '{}'.", value)),
+            };
+        } else {
+            return Error::from_str(&format!(
+                "Invalid JSON option '{}', expected 'ignore=<path>,...' or 'tolerance=<number>'.",
+                arg
+            ));
+        }
+    }
+    Ok(StreamMode::Json { ignore, tolerance })
+}
+
+/// Returns true if `haystack` contains `needle`.
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Returns the lines of a body, sorted.
+pub fn sorted_lines(body: &[u8]) -> Vec<&[u8]> {
+    let mut lines: Vec<&[u8]> = body.split(|byte| *byte == b'\n').collect();
+    lines.sort();
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let root = std::env::temp_dir().join(format!("parrot-history-{}", std::process::id()));
+        fs::create_dir_all(root.join(PARROT_PATH)).unwrap();
+        let data = DataManager::new(&root).ok().unwrap();
+        assert_eq!(data.get_history().ok(), Some(Vec::new()));
+
+        // Consecutive duplicates are merged
+        for command in &["f #net", "r *", "r *", "f #net"] {
+            data.add_history(command).ok().unwrap();
+        }
+        let expected: Vec<String> = vec!["f #net", "r *", "f #net"].into_iter().map(String::from).collect();
+        assert_eq!(data.get_history().ok(), Some(expected));
+
+        // Only the most recent commands are kept
+        for idx in 0..HISTORY_SIZE {
+            data.add_history(&format!("s {}", idx)).ok().unwrap();
+        }
+        let history = data.get_history().ok().unwrap();
+        assert_eq!(history.len(), HISTORY_SIZE);
+        assert_eq!(history[0], "s 0");
+        let content = fs::read_to_string(root.join(PARROT_PATH).join(HISTORY_PATH)).unwrap();
+        assert_eq!(content.lines().count(), HISTORY_SIZE);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_expected_code() {
+        // Should succeed
+        assert_eq!("exact".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Exact));
+        assert_eq!(" any ".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Ignore));
+        assert_eq!("ignore".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Ignore));
+        assert_eq!("nonzero".parse::<ExpectedCode>().ok(), Some(ExpectedCode::NonZero));
+        assert_eq!("2".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Set(vec![2])));
+        assert_eq!("-1".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Set(vec![-1])));
+        assert_eq!("0, 2".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Set(vec![0, 2])));
+        assert_eq!("1-3".parse::<ExpectedCode>().ok(), Some(ExpectedCode::Range(1, 3)));
+
+        // Should return an error
+        assert!("".parse::<ExpectedCode>().is_err());
+        assert!("3-1".parse::<ExpectedCode>().is_err());
+        assert!("1,a".parse::<ExpectedCode>().is_err());
+        assert!("some".parse::<ExpectedCode>().is_err());
+    }
+
+    #[test]
+    fn test_expected_code_matches() {
+        let range = ExpectedCode::Range(1, 3);
+        assert!(ExpectedCode::Exact.matches(Some(1), Some(1)));
+        assert!(!ExpectedCode::Exact.matches(Some(1), Some(2)));
+        assert!(ExpectedCode::Set(vec![0, 2]).matches(Some(1), Some(2)));
+        assert!(!ExpectedCode::Set(vec![0, 2]).matches(Some(1), Some(1)));
+        assert!(range.matches(Some(0), Some(3)));
+        assert!(!range.matches(Some(0), Some(4)));
+        assert!(!range.matches(Some(1), None));
+        assert!(ExpectedCode::NonZero.matches(Some(0), Some(7)));
+        assert!(!ExpectedCode::NonZero.matches(Some(1), Some(0)));
+        assert!(ExpectedCode::Ignore.matches(Some(0), None));
+    }
+
+    #[test]
+    fn test_stream_mode() {
+        // Should succeed
+        assert_eq!("exact".parse::<StreamMode>().ok(), Some(StreamMode::Exact));
+        assert_eq!(" ignore".parse::<StreamMode>().ok(), Some(StreamMode::Ignore));
+        assert_eq!("lines".parse::<StreamMode>().ok(), Some(StreamMode::Lines));
+        assert_eq!(
+            "contains  a warning ".parse::<StreamMode>().ok(),
+            Some(StreamMode::Contains(String::from("a warning")))
+        );
+        assert_eq!(
+            "regex ^v[0-9]+$".parse::<StreamMode>().ok(),
+            Some(StreamMode::Regex("^v[0-9]+$".parse().ok().unwrap()))
+        );
+        assert_eq!(
+            "json ignore=$.id,$.items[*].time tolerance=0.5".parse::<StreamMode>().ok(),
+            Some(StreamMode::Json {
+                ignore: vec![String::from("$.id"), String::from("$.items[*].time")],
+                tolerance: 0.5
+            })
+        );
+
+        // Should return an error
+        assert!("contains".parse::<StreamMode>().is_err());
+        assert!("regex (".parse::<StreamMode>().is_err());
+        assert!("exact foo".parse::<StreamMode>().is_err());
+        assert!("sorted".parse::<StreamMode>().is_err());
+        assert!("json tolerance=-1".parse::<StreamMode>().is_err());
+        assert!("json ignore=id".parse::<StreamMode>().is_err());
+        assert!("json pretty".parse::<StreamMode>().is_err());
+    }
+
+    #[test]
+    fn test_stream_mode_matches() {
+        let contains = StreamMode::Contains(String::from("warning"));
+        let regex = StreamMode::Regex("(?m)^v[0-9]+$".parse().ok().unwrap());
+        assert!(StreamMode::Exact.matches(b"a\nb", b"a\nb"));
+        assert!(!StreamMode::Exact.matches(b"a\nb", b"b\na"));
+        assert!(StreamMode::Ignore.matches(b"a", b"b"));
+        assert!(contains.matches(b"", b"a warning: unused"));
+        assert!(!contains.matches(b"warning", b"an error"));
+        assert!(regex.matches(b"", b"version\nv12\n"));
+        assert!(!regex.matches(b"", b"version 12\n"));
+        assert!(StreamMode::Lines.matches(b"a\nb\nb", b"b\na\nb"));
+        assert!(!StreamMode::Lines.matches(b"a\nb\nb", b"b\na\na"));
+        let json = StreamMode::Json {
+            ignore: vec![String::from("$.id")],
+            tolerance: 0.0,
+        };
+        assert!(json.matches(b"{\"a\": 1, \"id\": 2}", b"{\"id\":3,\n\"a\":1}"));
+        assert!(!json.matches(b"{\"a\": 1}", b"{\"a\": 2}"));
+        assert!(!json.matches(b"{\"a\": 1}", b"not json"));
+    }
+
+    #[test]
+    fn test_stream_mode_serde() {
+        let regex = StreamMode::Regex("^v[0-9]+$".parse().ok().unwrap());
+        let json = serde_json::to_string(&regex).unwrap();
+        assert_eq!(json, "{\"Regex\":\"^v[0-9]+$\"}");
+        assert_eq!(serde_json::from_str::<StreamMode>(&json).ok(), Some(regex));
+        assert!(serde_json::from_str::<StreamMode>("{\"Regex\":\"(\"}").is_err());
+    }
+
+    #[test]
+    fn test_whitespace() {
+        // Parsing
+        assert_eq!("exact".parse::<Whitespace>().ok(), Some(Whitespace::default()));
+        let parsed = "trailing, eol".parse::<Whitespace>().ok().unwrap();
+        assert!(parsed.trailing && parsed.line_endings && !parsed.blank_lines && !parsed.collapse);
+        assert_eq!(parsed.to_string(), "trailing,eol");
+        assert_eq!("all".parse::<Whitespace>().ok().unwrap().to_string(), "trailing,eol,blank,collapse");
+        assert!("tabs".parse::<Whitespace>().is_err());
+
+        // Normalization
+        let all = "all".parse::<Whitespace>().ok().unwrap();
+        assert_eq!(Whitespace::default().apply(b"a \r\n"), &b"a \r\n"[..]);
+        assert_eq!("eol".parse::<Whitespace>().ok().unwrap().apply(b"a \r\nb\r"), &b"a \nb"[..]);
+        assert_eq!("trailing".parse::<Whitespace>().ok().unwrap().apply(b"a \t\nb"), &b"a\nb"[..]);
+        assert_eq!("blank".parse::<Whitespace>().ok().unwrap().apply(b"a\n \n\nb"), &b"a\nb"[..]);
+        assert_eq!("collapse".parse::<Whitespace>().ok().unwrap().apply(b"a  \t b "), &b"a b "[..]);
+        assert_eq!(all.apply(b"a  b \r\n\r\nc\n"), all.apply(b"a b\nc"));
+    }
+}
@@ -78,6 +78,13 @@ impl SnapshotsManager {
         Ok(snap)
     }
 
+    /// Returns the path of the file holding a snapshot body.
+    pub fn get_path(&self, name: &str) -> PathBuf {
+        let mut name = name.to_owned();
+        name.push_str(FILE_EXTENSION);
+        self.path.join(name)
+    }
+
     /// Update a single snapshot, will not rise any errors if the snapshot already
     /// exists.
     fn update_snapshot(&self, snap: &SnapshotData) -> Result<(), Error> {
@@ -1,11 +1,64 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::ops::{Index, IndexMut, Range};
+use std::str::FromStr;
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-enum Node {
-    N((usize, usize)),
-    Root,
+/// The maximum number of occurrences of a line for it to be used as an anchor
+/// by the histogram algorithm.
+const MAX_CHAIN_LEN: usize = 64;
+
+/// The maximum number of lines of the common suffix looked up before, to find
+/// where it can be trimmed.
+const MAX_SUFFIX_PROBES: usize = 16;
+
+/// The algorithms available to compute a diff.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    /// A minimal edit script.
+    #[default]
+    Myers,
+    /// Anchors the diff on lines that are unique in both outputs.
+    Patience,
+    /// Anchors the diff on the least frequent common lines.
+    Histogram,
+}
+
+/// How the lines of a diff are laid out.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Side by side on wide terminals, unified otherwise.
+    #[default]
+    Auto,
+    /// Deleted lines followed by inserted lines.
+    Unified,
+    /// The old output on the left and the new one on the right.
+    Split,
+}
+
+/// A common region of `len` lines, starting at `old` and `new`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Anchor {
+    old: usize,
+    new: usize,
+    len: usize,
 }
 
+/// Returns the anchors of `old[old_range]` and `new[new_range]`.
+type FindAnchors = fn(&[&[u8]], Range<usize>, &[&[u8]], Range<usize>) -> Vec<Anchor>;
+
+/// A pending step of an anchored diff.
+enum Task {
+    Diff(Range<usize>, Range<usize>),
+    Keep(Range<usize>),
+}
+
+/// The changed byte ranges of an old line and of a new line.
+pub type Spans = (Vec<Range<usize>>, Vec<Range<usize>>);
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum DiffLine<'a> {
     Keep(&'a [u8]),
@@ -13,91 +66,686 @@ pub enum DiffLine<'a> {
     Insert(&'a [u8]),
 }
 
-// Heavily inspired by https://github.com/tamuhey/seqdiff/blob/master/src/lib.rs.
+/// A group of changes surrounded by unchanged context lines.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hunk {
+    /// The first line of the hunk in the old output, starting at 1.
+    pub old_start: usize,
+    pub old_len: usize,
+    /// The first line of the hunk in the new output, starting at 1.
+    pub new_start: usize,
+    pub new_len: usize,
+    /// The indices of the hunk's lines in the diff.
+    pub lines: Range<usize>,
+}
+
+/// Returns the diff between two slices of bytes slices, computed with the
+/// given algorithm.
+pub fn get_diff_with<'a>(algorithm: Algorithm, old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<DiffLine<'a>> {
+    match algorithm {
+        Algorithm::Myers => get_diff(old, new),
+        Algorithm::Patience => get_anchored_diff(old, new, find_patience_anchors),
+        Algorithm::Histogram => get_anchored_diff(old, new, find_histogram_anchors),
+    }
+}
+
 /// Returns the shortest edit script (or diff) between two slices of bytes slices.
+///
+/// This is a linear space variant of Myers' algorithm: rather than remembering
+/// every visited node to backtrack, the greedy forward search is run again to
+/// find the snake it follows halfway, then both halves are diffed recursively.
+/// The script is the one found by the greedy search with backtracking, so the
+/// common suffix is only trimmed from its first line that occurs nowhere
+/// before, see `trimmable_suffix_len`.
 pub fn get_diff<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<DiffLine<'a>> {
-    let n = old.len();
-    let m = new.len();
-    let bound = n + m;
-    let get_y = |x, k| x + bound - k;
-    let mut v = vec![0; 2 * bound + 1];
-    let mut nodes_map = HashMap::new();
-
-    // Forward pass
-    'outer: for d in 0..=bound {
-        for k in ((bound - d)..=bound + d).step_by(2) {
-            let (mut x, parent) = if d == 0 {
-                // Initial state
-                (0, Node::Root)
-            } else if k == (bound - d) || k != (bound + d) && v[k - 1] < v[k + 1] {
-                // Move downward
-                let px = v[k + 1];
-                (px, Node::N((px, get_y(px, k + 1))))
+    let mut search = Search {
+        v: V::new(old.len() + new.len()),
+        snakes: V::new(old.len() + new.len()),
+    };
+    let mut diff = Vec::with_capacity(std::cmp::max(old.len(), new.len()));
+    let prefix_len = common_prefix_len(old, new);
+    diff.extend(old[..prefix_len].iter().map(|line| DiffLine::Keep(line)));
+    let suffix_len = trimmable_suffix_len(&old[prefix_len..], &new[prefix_len..]);
+    let (old_range, new_range) = (prefix_len..old.len() - suffix_len, prefix_len..new.len() - suffix_len);
+    let (d, _) = find_snake(old, old_range.clone(), new, new_range.clone(), 0, &mut search);
+    conquer(old, old_range, new, new_range, d, &mut search, &mut diff);
+    diff.extend(old[old.len() - suffix_len..].iter().map(|line| DiffLine::Keep(line)));
+    diff
+}
+
+/// A value for each diagonal k, where k = x - y may be negative.
+struct V<T> {
+    offset: isize,
+    v: Vec<T>,
+}
+
+impl<T: Default + Clone> V<T> {
+    fn new(max_d: usize) -> V<T> {
+        V {
+            offset: max_d as isize,
+            v: vec![T::default(); 2 * max_d + 1],
+        }
+    }
+}
+
+impl<T> Index<isize> for V<T> {
+    type Output = T;
+
+    fn index(&self, k: isize) -> &T {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl<T> IndexMut<isize> for V<T> {
+    fn index_mut(&mut self, k: isize) -> &mut T {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+/// A run of unchanged lines on the diagonal k, from x = `start` to `end`.
+#[derive(Debug, Default, Clone, Copy)]
+struct Snake {
+    k: isize,
+    start: usize,
+    end: usize,
+}
+
+/// The state of the greedy search, for each diagonal: the furthest reaching
+/// x coordinate and the snake followed halfway to reach it.
+struct Search {
+    v: V<usize>,
+    snakes: V<Snake>,
+}
+
+/// Pushes the diff of `old[old_range]` and `new[new_range]`, which differ by
+/// `d` edits.
+fn conquer<'a>(
+    old: &[&'a [u8]],
+    mut old_range: Range<usize>,
+    new: &[&'a [u8]],
+    mut new_range: Range<usize>,
+    d: usize,
+    search: &mut Search,
+    diff: &mut Vec<DiffLine<'a>>,
+) {
+    // Trim common prefix, the search would follow it first
+    let prefix_len = common_prefix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    diff.extend(old[old_range.start..old_range.start + prefix_len].iter().map(|line| DiffLine::Keep(line)));
+    old_range.start += prefix_len;
+    new_range.start += prefix_len;
+
+    if old_range.is_empty() || new_range.is_empty() {
+        diff.extend(old[old_range].iter().map(|line| DiffLine::Delete(line)));
+        diff.extend(new[new_range].iter().map(|line| DiffLine::Insert(line)));
+    } else if d == 1 {
+        // A single edit, followed by unchanged lines
+        if old_range.len() > new_range.len() {
+            diff.push(DiffLine::Delete(old[old_range.start]));
+            old_range.start += 1;
+        } else {
+            diff.push(DiffLine::Insert(new[new_range.start]));
+        }
+        diff.extend(old[old_range].iter().map(|line| DiffLine::Keep(line)));
+    } else {
+        let (_, snake) = find_snake(old, old_range.clone(), new, new_range.clone(), d / 2, search);
+        let (x, y) = (old_range.start + snake.start, new_range.start + (snake.start as isize - snake.k) as usize);
+        let len = snake.end - snake.start;
+        conquer(old, old_range.start..x, new, new_range.start..y, d / 2, search, diff);
+        diff.extend(old[x..x + len].iter().map(|line| DiffLine::Keep(line)));
+        conquer(old, x + len..old_range.end, new, y + len..new_range.end, d - d / 2, search, diff);
+    }
+}
+
+/// Runs the greedy search of Myers' algorithm on `old[old_range]` and
+/// `new[new_range]`, and returns the number of edits between them along with
+/// the snake that leads to their end after `half` edits.
+fn find_snake(
+    old: &[&[u8]],
+    old_range: Range<usize>,
+    new: &[&[u8]],
+    new_range: Range<usize>,
+    half: usize,
+    search: &mut Search,
+) -> (usize, Snake) {
+    let Search { v, snakes } = search;
+    let n = old_range.len();
+    let m = new_range.len();
+    for d in 0..=(n + m) as isize {
+        for k in (-d..=d).step_by(2) {
+            // Move downward or rightward from the furthest neighbour, favouring
+            // downward moves on ties
+            let (mut x, previous) = if d == 0 {
+                (0, 0)
+            } else if k == -d || (k != d && v[k - 1] < v[k + 1]) {
+                (v[k + 1], k + 1)
             } else {
-                // Move rightward
-                let px = v[k - 1];
-                (px + 1, Node::N((px, get_y(px, k - 1))))
+                (v[k - 1] + 1, k - 1)
             };
-            let mut y = get_y(x, k);
-            nodes_map.insert(Node::N((x, y)), parent);
-
-            // Take as much diagonals as possible
-            while x < n && y < m && old[x] == new[y] {
-                nodes_map.insert(Node::N((x + 1, y + 1)), Node::N((x, y)));
-                x += 1;
-                y += 1;
+            let start = x;
+            let y = (x as isize - k) as usize;
+            if x < n && y < m {
+                x += common_prefix_len(
+                    &old[old_range.start + x..old_range.end],
+                    &new[new_range.start + y..new_range.end],
+                );
             }
-
             v[k] = x;
-            if x >= n && y >= m {
-                // Done
-                break 'outer;
+            snakes[k] = if d as usize == half {
+                Snake { k, start, end: x }
+            } else {
+                snakes[previous]
+            };
+            if x >= n && y + x - start >= m {
+                return (d as usize, snakes[k]);
             }
         }
     }
+    unreachable!("Internal error: diff search did not reach the end.")
+}
 
-    // Backtrack
-    let mut current = Node::N((n, m));
-    let mut diff = Vec::new();
-    loop {
-        let previous = *nodes_map
-            .get(&current)
-            .expect("Internal error: failed to backtrack diff.");
-        if previous == Node::Root {
-            break;
-        }
-        let (prev_x, prev_y) = get_coordinates(previous);
-        let (x, y) = get_coordinates(current);
-        if x == prev_x && y == prev_y + 1 {
-            diff.push(DiffLine::Insert(new[prev_y]));
-        } else if x == prev_x + 1 && y == prev_y {
-            diff.push(DiffLine::Delete(old[prev_x]));
-        } else if x == prev_x + 1 && y == prev_y + 1 {
-            diff.push(DiffLine::Keep(old[prev_x]));
+/// Returns a diff built around the anchors returned by `find_anchors`, regions
+/// between anchors are diffed the same way and regions without anchors fall
+/// back to Myers' algorithm.
+fn get_anchored_diff<'a>(
+    old: &[&'a [u8]],
+    new: &[&'a [u8]],
+    find_anchors: FindAnchors,
+) -> Vec<DiffLine<'a>> {
+    let mut diff = Vec::with_capacity(std::cmp::max(old.len(), new.len()));
+    // Tasks are executed in reverse order
+    let mut tasks = vec![Task::Diff(0..old.len(), 0..new.len())];
+    while let Some(task) = tasks.pop() {
+        let (mut old_range, mut new_range) = match task {
+            Task::Keep(range) => {
+                diff.extend(old[range].iter().map(|line| DiffLine::Keep(line)));
+                continue;
+            }
+            Task::Diff(old_range, new_range) => (old_range, new_range),
+        };
+        // Trim common prefix and suffix
+        let prefix_len = common_prefix_len(&old[old_range.clone()], &new[new_range.clone()]);
+        let suffix_len = common_suffix_len(&old[old_range.start + prefix_len..old_range.end], &new[new_range.start + prefix_len..new_range.end]);
+        tasks.push(Task::Keep(old_range.end - suffix_len..old_range.end));
+        diff.extend(old[old_range.start..old_range.start + prefix_len].iter().map(|line| DiffLine::Keep(line)));
+        old_range = old_range.start + prefix_len..old_range.end - suffix_len;
+        new_range = new_range.start + prefix_len..new_range.end - suffix_len;
+
+        let anchors = if old_range.is_empty() || new_range.is_empty() {
+            Vec::new()
         } else {
-            panic!(
-                "Internal error: malformed path in diff backtrack: from ({}, {}) to ({}, {}).",
-                prev_x, prev_y, x, y
-            )
+            find_anchors(old, old_range.clone(), new, new_range.clone())
+        };
+        if anchors.is_empty() {
+            diff.extend(get_diff(&old[old_range], &new[new_range]));
+            continue;
+        }
+        let (mut old_end, mut new_end) = (old_range.end, new_range.end);
+        for anchor in anchors.into_iter().rev() {
+            tasks.push(Task::Diff(anchor.old + anchor.len..old_end, anchor.new + anchor.len..new_end));
+            tasks.push(Task::Keep(anchor.old..anchor.old + anchor.len));
+            old_end = anchor.old;
+            new_end = anchor.new;
         }
-        current = previous;
+        tasks.push(Task::Diff(old_range.start..old_end, new_range.start..new_end));
     }
-    diff.reverse();
     diff
 }
 
-/// Extracts the x and y coordinate of a node.
-fn get_coordinates(node: Node) -> (usize, usize) {
-    match node {
-        Node::Root => (0, 0),
-        Node::N((x, y)) => (x, y),
+/// Returns the longest increasing sequence of lines that are unique in both
+/// `old[old_range]` and `new[new_range]`.
+fn find_patience_anchors(
+    old: &[&[u8]],
+    old_range: Range<usize>,
+    new: &[&[u8]],
+    new_range: Range<usize>,
+) -> Vec<Anchor> {
+    // Count the occurrences of each line, and remember their last position
+    let mut lines: HashMap<&[u8], (usize, usize, usize, usize)> = HashMap::new();
+    for idx in old_range {
+        let entry = lines.entry(old[idx]).or_insert((0, 0, 0, 0));
+        entry.0 += 1;
+        entry.1 = idx;
+    }
+    for idx in new_range {
+        if let Some(entry) = lines.get_mut(new[idx]) {
+            entry.2 += 1;
+            entry.3 = idx;
+        }
+    }
+    let mut unique: Vec<(usize, usize)> = lines
+        .values()
+        .filter(|(old_count, _, new_count, _)| *old_count == 1 && *new_count == 1)
+        .map(|(_, old_idx, _, new_idx)| (*old_idx, *new_idx))
+        .collect();
+    unique.sort_unstable();
+
+    // Patience sorting: `piles` holds the index of the top of each pile,
+    // `previous` the top of the previous pile when a line was pushed
+    let mut piles: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = Vec::with_capacity(unique.len());
+    for (idx, (_, new_idx)) in unique.iter().enumerate() {
+        let pile = piles.partition_point(|top| unique[*top].1 < *new_idx);
+        previous.push(if pile > 0 { Some(piles[pile - 1]) } else { None });
+        if pile == piles.len() {
+            piles.push(idx);
+        } else {
+            piles[pile] = idx;
+        }
+    }
+    let mut anchors = Vec::new();
+    let mut current = piles.last().copied();
+    while let Some(idx) = current {
+        let (old, new) = unique[idx];
+        anchors.push(Anchor { old, new, len: 1 });
+        current = previous[idx];
+    }
+    anchors.reverse();
+    anchors
+}
+
+/// Returns the longest common region of `old[old_range]` and
+/// `new[new_range]` that contains one of their least frequent lines.
+fn find_histogram_anchors(
+    old: &[&[u8]],
+    old_range: Range<usize>,
+    new: &[&[u8]],
+    new_range: Range<usize>,
+) -> Vec<Anchor> {
+    let mut occurrences: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for idx in old_range.clone() {
+        occurrences.entry(old[idx]).or_default().push(idx);
+    }
+    let middle = (new_range.start + new_range.end) / 2;
+    // The best region so far, and the number of occurrences of its line
+    let mut best: Option<(usize, Anchor)> = None;
+    let mut new_idx = new_range.start;
+    while new_idx < new_range.end {
+        let mut next_idx = new_idx + 1;
+        let positions = match occurrences.get(new[new_idx]) {
+            Some(positions) if positions.len() <= MAX_CHAIN_LEN => positions,
+            _ => {
+                new_idx = next_idx;
+                continue;
+            }
+        };
+        if matches!(best, Some((count, _)) if positions.len() > count) {
+            new_idx = next_idx;
+            continue;
+        }
+        for &old_idx in positions {
+            let (mut old_start, mut new_start) = (old_idx, new_idx);
+            while old_start > old_range.start && new_start > new_range.start && old[old_start - 1] == new[new_start - 1] {
+                old_start -= 1;
+                new_start -= 1;
+            }
+            let (mut old_end, mut new_end) = (old_idx + 1, new_idx + 1);
+            while old_end < old_range.end && new_end < new_range.end && old[old_end] == new[new_end] {
+                old_end += 1;
+                new_end += 1;
+            }
+            let anchor = Anchor {
+                old: old_start,
+                new: new_start,
+                len: old_end - old_start,
+            };
+            // Ties are broken by the distance to the middle, so that the
+            // remaining regions are balanced
+            let distance = |anchor: &Anchor| (anchor.new + anchor.len / 2).abs_diff(middle);
+            let is_better = match best {
+                Some((count, best_anchor)) => {
+                    positions.len() < count
+                        || (positions.len() == count && anchor.len > best_anchor.len)
+                        || (positions.len() == count
+                            && anchor.len == best_anchor.len
+                            && distance(&anchor) < distance(&best_anchor))
+                }
+                None => true,
+            };
+            if is_better {
+                best = Some((positions.len(), anchor));
+            }
+            next_idx = std::cmp::max(next_idx, new_end);
+        }
+        new_idx = next_idx;
+    }
+    best.map(|(_, anchor)| vec![anchor]).unwrap_or_default()
+}
+
+/// Returns the length of the common prefix of two slices.
+fn common_prefix_len(old: &[&[u8]], new: &[&[u8]]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+/// Returns the length of the common suffix of two slices.
+fn common_suffix_len(old: &[&[u8]], new: &[&[u8]]) -> usize {
+    old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| a == b).count()
+}
+
+/// Returns the length of the common suffix that can be trimmed without
+/// changing the script of the greedy search. That search keeps lines as early
+/// as possible, so a suffix line occurring before in either slice may be kept
+/// there instead, but the suffix following a line that occurs nowhere before
+/// is always kept as is.
+fn trimmable_suffix_len(old: &[&[u8]], new: &[&[u8]]) -> usize {
+    let suffix_len = common_suffix_len(old, new);
+    if suffix_len == 0 {
+        return 0;
+    }
+    let (old_len, new_len) = (old.len() - suffix_len, new.len() - suffix_len);
+    for idx in 0..std::cmp::min(suffix_len, MAX_SUFFIX_PROBES) {
+        // The suffix lines before `idx` are also in `old[..old_len + idx]`
+        let line = &old[old_len + idx];
+        if !old[..old_len + idx].contains(line) && !new[..new_len].contains(line) {
+            return suffix_len - idx;
+        }
+    }
+    0
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    /// Parses a diff algorithm, either `myers`, `patience` or `histogram`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "myers" => Ok(Algorithm::Myers),
+            "patience" => Ok(Algorithm::Patience),
+            "histogram" => Ok(Algorithm::Histogram),
+            s => Err(format!(
+                "Invalid diff algorithm '{}', expected 'myers', 'patience' or 'histogram'.",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Algorithm::Myers => write!(f, "myers"),
+            Algorithm::Patience => write!(f, "patience"),
+            Algorithm::Histogram => write!(f, "histogram"),
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    /// Parses a diff layout, either `auto`, `unified` or `split`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(Layout::Auto),
+            "unified" => Ok(Layout::Unified),
+            "split" => Ok(Layout::Split),
+            s => Err(format!("Invalid diff layout '{}', expected 'auto', 'unified' or 'split'.", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Layout::Auto => write!(f, "auto"),
+            Layout::Unified => write!(f, "unified"),
+            Layout::Split => write!(f, "split"),
+        }
+    }
+}
+
+/// Groups the changes of a diff into hunks with up to `context` unchanged
+/// lines before and after them. Changes separated by less than twice the
+/// context are part of the same hunk.
+pub fn get_hunks(diff: &[DiffLine], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Keep(_)))
+        .map(|(idx, _)| idx)
+        .collect();
+    // Gather the ranges of lines to display
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for idx in changes {
+        let start = idx.saturating_sub(context);
+        let end = std::cmp::min(idx + context + 1, diff.len());
+        match ranges.last_mut() {
+            Some(last) if last.end >= start => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    // Compute line numbers
+    let (mut old_line, mut new_line, mut pos) = (0, 0, 0);
+    let mut hunks = Vec::new();
+    for range in ranges {
+        for line in &diff[pos..range.start] {
+            count_line(line, &mut old_line, &mut new_line);
+        }
+        let (old_start, new_start) = (old_line, new_line);
+        for line in &diff[range.clone()] {
+            count_line(line, &mut old_line, &mut new_line);
+        }
+        pos = range.end;
+        hunks.push(Hunk {
+            old_start: old_start + 1,
+            old_len: old_line - old_start,
+            new_start: new_start + 1,
+            new_len: new_line - new_start,
+            lines: range,
+        });
+    }
+    hunks
+}
+
+/// Writes the differences between two bodies as a unified patch, which can
+/// be applied with `patch` or `git apply`. The file at `path` must exist, it
+/// is emptied rather than deleted.
+pub fn write_patch<W: Write>(
+    path: &str,
+    old: &[u8],
+    new: &[u8],
+    algorithm: Algorithm,
+    context: usize,
+    buffer: &mut W,
+) -> io::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let old_lines: Vec<&[u8]> = old.split_inclusive(|byte| *byte == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new.split_inclusive(|byte| *byte == b'\n').collect();
+    let diff = get_diff_with(algorithm, &old_lines, &new_lines);
+    writeln!(buffer, "--- a/{}", path)?;
+    writeln!(buffer, "+++ b/{}", path)?;
+    for hunk in get_hunks(&diff, context) {
+        // Empty ranges start at the line before them
+        let old_start = if hunk.old_len == 0 { hunk.old_start - 1 } else { hunk.old_start };
+        let new_start = if hunk.new_len == 0 { hunk.new_start - 1 } else { hunk.new_start };
+        writeln!(buffer, "@@ -{},{} +{},{} @@", old_start, hunk.old_len, new_start, hunk.new_len)?;
+        for line in &diff[hunk.lines] {
+            let (prefix, line) = match line {
+                DiffLine::Keep(line) => (b' ', line),
+                DiffLine::Delete(line) => (b'-', line),
+                DiffLine::Insert(line) => (b'+', line),
+            };
+            buffer.write_all(&[prefix])?;
+            buffer.write_all(line)?;
+            if !line.ends_with(b"\n") {
+                buffer.write_all(b"\n\\ No newline at end of file\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Advances the old and new line numbers past a line of the diff.
+fn count_line(line: &DiffLine, old_line: &mut usize, new_line: &mut usize) {
+    match line {
+        DiffLine::Keep(_) => {
+            *old_line += 1;
+            *new_line += 1;
+        }
+        DiffLine::Delete(_) => *old_line += 1,
+        DiffLine::Insert(_) => *new_line += 1,
+    }
+}
+
+/// Returns the byte ranges that changed between two versions of a line, for
+/// the old and the new line respectively, by diffing their words.
+/// Returns None if the lines are too different for the ranges to be useful.
+pub fn get_changed_spans(old: &[u8], new: &[u8]) -> Option<Spans> {
+    let old_words = split_words(old);
+    let new_words = split_words(new);
+    let mut old_spans: Vec<Range<usize>> = Vec::new();
+    let mut new_spans: Vec<Range<usize>> = Vec::new();
+    let (mut old_pos, mut new_pos, mut kept) = (0, 0, 0);
+    for line in get_diff(&old_words, &new_words) {
+        match line {
+            DiffLine::Keep(word) => {
+                old_pos += word.len();
+                new_pos += word.len();
+                kept += word.len();
+            }
+            DiffLine::Delete(word) => {
+                push_span(&mut old_spans, old_pos..old_pos + word.len());
+                old_pos += word.len();
+            }
+            DiffLine::Insert(word) => {
+                push_span(&mut new_spans, new_pos..new_pos + word.len());
+                new_pos += word.len();
+            }
+        }
+    }
+    if kept * 2 < std::cmp::min(old.len(), new.len()) {
+        None
+    } else {
+        Some((old_spans, new_spans))
+    }
+}
+
+/// Appends a span, merging it with the previous one if they are contiguous.
+fn push_span(spans: &mut Vec<Range<usize>>, span: Range<usize>) {
+    match spans.last_mut() {
+        Some(last) if last.end == span.start => last.end = span.end,
+        _ => spans.push(span),
+    }
+}
+
+/// Splits a line into words: runs of alphanumeric characters, runs of
+/// whitespaces, or any other single character.
+fn split_words(line: &[u8]) -> Vec<&[u8]> {
+    let class = |byte: u8| {
+        if byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii() {
+            1
+        } else if byte.is_ascii_whitespace() {
+            2
+        } else {
+            0
+        }
+    };
+    let mut words = Vec::new();
+    let mut start = 0;
+    for idx in 1..=line.len() {
+        let ends_word = idx == line.len() || class(line[idx]) == 0 || class(line[idx]) != class(line[idx - 1]);
+        if ends_word {
+            words.push(&line[start..idx]);
+            start = idx;
+        }
     }
+    words
 }
 
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use rand::{thread_rng, Rng};
+    use std::collections::HashMap;
+
+    #[derive(PartialEq, Eq, Hash, Clone, Copy)]
+    enum Node {
+        N((usize, usize)),
+        Root,
+    }
+
+    // Heavily inspired by https://github.com/tamuhey/seqdiff/blob/master/src/lib.rs.
+    /// The previous, quadratic space, implementation of `get_diff`.
+    fn get_diff_reference<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<DiffLine<'a>> {
+        let n = old.len();
+        let m = new.len();
+        let bound = n + m;
+        let get_y = |x, k| x + bound - k;
+        let mut v = vec![0; 2 * bound + 1];
+        let mut nodes_map = HashMap::new();
+
+        // Forward pass
+        'outer: for d in 0..=bound {
+            for k in ((bound - d)..=bound + d).step_by(2) {
+                let (mut x, parent) = if d == 0 {
+                    // Initial state
+                    (0, Node::Root)
+                } else if k == (bound - d) || k != (bound + d) && v[k - 1] < v[k + 1] {
+                    // Move downward
+                    let px = v[k + 1];
+                    (px, Node::N((px, get_y(px, k + 1))))
+                } else {
+                    // Move rightward
+                    let px = v[k - 1];
+                    (px + 1, Node::N((px, get_y(px, k - 1))))
+                };
+                let mut y = get_y(x, k);
+                nodes_map.insert(Node::N((x, y)), parent);
+
+                // Take as much diagonals as possible
+                while x < n && y < m && old[x] == new[y] {
+                    nodes_map.insert(Node::N((x + 1, y + 1)), Node::N((x, y)));
+                    x += 1;
+                    y += 1;
+                }
+
+                v[k] = x;
+                if x >= n && y >= m {
+                    // Done
+                    break 'outer;
+                }
+            }
+        }
+
+        // Backtrack
+        let mut current = Node::N((n, m));
+        let mut diff = Vec::new();
+        loop {
+            let previous = *nodes_map
+                .get(&current)
+                .expect("Internal error: failed to backtrack diff.");
+            if previous == Node::Root {
+                break;
+            }
+            let (prev_x, prev_y) = get_coordinates(previous);
+            let (x, y) = get_coordinates(current);
+            if x == prev_x && y == prev_y + 1 {
+                diff.push(DiffLine::Insert(new[prev_y]));
+            } else if x == prev_x + 1 && y == prev_y {
+                diff.push(DiffLine::Delete(old[prev_x]));
+            } else if x == prev_x + 1 && y == prev_y + 1 {
+                diff.push(DiffLine::Keep(old[prev_x]));
+            } else {
+                panic!(
+                    "Internal error: malformed path in diff backtrack: from ({}, {}) to ({}, {}).",
+                    prev_x, prev_y, x, y
+                )
+            }
+            current = previous;
+        }
+        diff.reverse();
+        diff
+    }
+
+    /// Extracts the x and y coordinate of a node.
+    fn get_coordinates(node: Node) -> (usize, usize) {
+        match node {
+            Node::Root => (0, 0),
+            Node::N((x, y)) => (x, y),
+        }
+    }
 
     #[test]
     fn test_diff() {
@@ -126,4 +774,137 @@ mod tests {
         ];
         assert_eq!(diff, expected_diff);
     }
+
+    #[test]
+    fn test_changed_spans() {
+        assert_eq!(split_words(b"v1.2 is_ok  !"), vec![&b"v1"[..], b".", b"2", b" ", b"is_ok", b"  ", b"!"]);
+        assert_eq!(split_words(b""), Vec::<&[u8]>::new());
+
+        let old = b"[info] running parrot v1.2.3 on linux";
+        let new = b"[info] running parrot v1.2.4 on linux";
+        assert_eq!(get_changed_spans(old, new), Some((vec![Range { start: 27, end: 28 }], vec![Range { start: 27, end: 28 }])));
+
+        let old = b"a b c";
+        let new = b"a c d";
+        assert_eq!(get_changed_spans(old, new), Some((vec![Range { start: 2, end: 4 }], vec![Range { start: 3, end: 5 }])));
+
+        // Unrelated lines
+        assert_eq!(get_changed_spans(b"hello world", b"goodbye moon"), None);
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old: Vec<&[u8]> = b"0123456789abcdef".chunks(1).collect();
+        let mut new = old.clone();
+        new[2] = b"x";
+        new.insert(4, b"y");
+        new.remove(14);
+        let diff = get_diff(&old, &new);
+        let hunks = get_hunks(&diff, 1);
+        let expected_hunks = vec![
+            Hunk {
+                old_start: 2,
+                old_len: 4,
+                new_start: 2,
+                new_len: 5,
+                lines: Range { start: 1, end: 7 },
+            },
+            Hunk {
+                old_start: 13,
+                old_len: 3,
+                new_start: 14,
+                new_len: 2,
+                lines: Range { start: 14, end: 17 },
+            },
+        ];
+        assert_eq!(hunks, expected_hunks);
+
+        // Without context, only changes are kept
+        assert_eq!(get_hunks(&diff, 0).len(), 3);
+        // Without changes, there are no hunks
+        assert_eq!(get_hunks(&get_diff(&old, &old), 3), vec![]);
+    }
+
+    #[test]
+    fn test_write_patch() {
+        let write = |old: &[u8], new: &[u8]| {
+            let mut patch = Vec::new();
+            write_patch("x.txt", old, new, Algorithm::Myers, 1, &mut patch).unwrap();
+            String::from_utf8(patch).unwrap()
+        };
+        assert_eq!(
+            write(b"a\nb\nc\nd\n", b"a\nB\nc\nd\n"),
+            "--- a/x.txt\n+++ b/x.txt\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+        assert_eq!(
+            write(b"a\nb", b"a\nb\n"),
+            "--- a/x.txt\n+++ b/x.txt\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n"
+        );
+        assert_eq!(write(b"", b"a\n"), "--- a/x.txt\n+++ b/x.txt\n@@ -0,0 +1,1 @@\n+a\n");
+        assert_eq!(write(b"a\n", b""), "--- a/x.txt\n+++ b/x.txt\n@@ -1,1 +0,0 @@\n-a\n");
+        assert_eq!(write(b"a\n", b"a\n"), "");
+    }
+
+    /// Asserts that a diff transforms old into new.
+    fn assert_valid(diff: &[DiffLine], old: &[&[u8]], new: &[&[u8]]) {
+        let before: Vec<&[u8]> = diff
+            .iter()
+            .filter_map(|line| match line {
+                DiffLine::Keep(line) | DiffLine::Delete(line) => Some(*line),
+                DiffLine::Insert(_) => None,
+            })
+            .collect();
+        let after: Vec<&[u8]> = diff
+            .iter()
+            .filter_map(|line| match line {
+                DiffLine::Keep(line) | DiffLine::Insert(line) => Some(*line),
+                DiffLine::Delete(_) => None,
+            })
+            .collect();
+        assert_eq!(before, old);
+        assert_eq!(after, new);
+    }
+
+    #[test]
+    fn test_diff_against_reference() {
+        let alphabet: Vec<&[u8]> = vec![b"a", b"b", b"c", b"{", b"}", b""];
+        let mut rng = thread_rng();
+        let random_lines = |rng: &mut rand::rngs::ThreadRng| -> Vec<&[u8]> {
+            let len = rng.gen_range(0, 40);
+            (0..len).map(|_| alphabet[rng.gen_range(0, alphabet.len())]).collect()
+        };
+        for _ in 0..1000 {
+            let mut old = random_lines(&mut rng);
+            let mut new = random_lines(&mut rng);
+            // A common suffix, that may be trimmed
+            let suffix = random_lines(&mut rng);
+            old.extend(&suffix);
+            new.extend(&suffix);
+            let diff = get_diff(&old, &new);
+            assert_eq!(diff, get_diff_reference(&old, &new), "{:?} {:?}", old, new);
+
+            // The scripts transform old into new
+            assert_valid(&diff, &old, &new);
+            assert_valid(&get_diff_with(Algorithm::Patience, &old, &new), &old, &new);
+            assert_valid(&get_diff_with(Algorithm::Histogram, &old, &new), &old, &new);
+        }
+    }
+
+    #[test]
+    fn test_anchored_diff() {
+        let old: Vec<&[u8]> = vec![b"a() {", b"x", b"}", b"", b"b() {", b"y", b"}"];
+        let new: Vec<&[u8]> = vec![b"a() {", b"x", b"}", b"", b"c() {", b"z", b"}", b"", b"b() {", b"y", b"}"];
+        let mut expected_diff: Vec<DiffLine> = old[..4].iter().map(|line| DiffLine::Keep(line)).collect();
+        expected_diff.extend(new[4..8].iter().map(|line| DiffLine::Insert(line)));
+        expected_diff.extend(old[4..].iter().map(|line| DiffLine::Keep(line)));
+        assert_eq!(get_diff_with(Algorithm::Patience, &old, &new), expected_diff);
+        assert_eq!(get_diff_with(Algorithm::Histogram, &old, &new), expected_diff);
+
+        // Patience anchors on unique lines only
+        let old: Vec<&[u8]> = vec![b"{", b"a", b"}", b"{", b"b", b"}"];
+        let new: Vec<&[u8]> = vec![b"{", b"b", b"}", b"{", b"a", b"}"];
+        let anchors = find_patience_anchors(&old, 0..old.len(), &new, 0..new.len());
+        assert_eq!(anchors.len(), 1);
+        assert_valid(&get_diff_with(Algorithm::Patience, &old, &new), &old, &new);
+    }
 }
@@ -1,4 +1,4 @@
-use std::process::{Command, Output};
+use std::process::{Command, ExitStatus, Output};
 use std::path::Path;
 
 use crate::error::{wrap, Error};
@@ -10,3 +10,17 @@ pub fn execute<P: AsRef<Path>>(cmd: &str, dir: P) -> Result<Output, Error> {
     let output = wrap(process.output(), "Could not run command")?;
     Ok(output)
 }
+
+/// Returns the signal that terminated a process, if any, and whether a core
+/// was dumped.
+#[cfg(unix)]
+pub fn get_signal(status: &ExitStatus) -> (Option<i32>, bool) {
+    use std::os::unix::process::ExitStatusExt;
+    (status.signal(), status.core_dumped())
+}
+
+/// Returns the signal that terminated a process, signals only exist on unix.
+#[cfg(not(unix))]
+pub fn get_signal(_status: &ExitStatus) -> (Option<i32>, bool) {
+    (None, false)
+}
@@ -1,21 +1,31 @@
-use std::io::{stdin, stdout, Write};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{stdin, stdout, BufRead, Write};
 use std::path::PathBuf;
+use std::process::Output;
+use std::rc::Rc;
 
-use crate::data::{DataManager, Snapshot, SnapshotStatus};
+use crate::ansi::StripWriter;
+use crate::data::{DataManager, Snapshot, SnapshotData, SnapshotStatus};
+use crate::diff;
+use crate::diff::{Algorithm, Layout};
 use crate::editor;
-use crate::error::{Error, Log};
+use crate::error::{wrap, Error, Log};
 use crate::parser;
 use crate::term;
-use crate::term::{BoxedWriter, Input, SeparatorKind};
+use crate::term::{BoxedWriter, Console, Input, SeparatorKind};
 
-use parser::{parse, Command, Filter, Target};
+use parser::{parse, Command, TagChange, Target};
 use util::*;
 
 mod cmd;
 mod repl;
 mod util;
 
-pub use repl::View;
+pub use repl::{TagStats, View};
+
+/// The tag given to flaky snapshots.
+const FLAKY_TAG: &str = "flaky";
 
 /// The result of a command execution, which may ask for termination or not.
 pub enum ReplStatus {
@@ -27,16 +37,31 @@ pub struct Context {
     path: PathBuf,
     data: DataManager,
     theme: term::Theme,
+    diff_options: term::DiffOptions,
 }
 
 impl Context {
     /// Creates a new context.
-    pub fn new(path: PathBuf) -> Result<Context, Error> {
+    /// The diff algorithm and layout default to the project's ones if none
+    /// are given.
+    pub fn new(
+        path: PathBuf,
+        context: usize,
+        algorithm: Option<Algorithm>,
+        layout: Option<Layout>,
+    ) -> Result<Context, Error> {
         let data = DataManager::new(&path)?;
+        let config = data.get_config()?;
+        let diff_options = term::DiffOptions {
+            context,
+            algorithm: algorithm.unwrap_or(config.diff),
+            layout: layout.unwrap_or(config.layout),
+        };
         Ok(Context {
             path,
             data,
             theme: term::Theme::new(),
+            diff_options,
         })
     }
 
@@ -47,12 +72,13 @@ impl Context {
     }
 
     /// Handles add subcommand.
-    pub fn add(&mut self, cmd: &str, name: &Option<String>, yes: bool) {
+    pub fn add(&mut self, cmd: &str, name: &Option<String>, mut settings: editor::Settings, yes: bool) {
         let snap = cmd::execute(&cmd, &self.path).unwrap_log();
         let save = if yes {
             true
         } else {
-            term::snap_preview(&snap, &mut stdout(), &self.theme);
+            let (signal, core_dumped) = cmd::get_signal(&snap.status);
+            term::snap_preview(&snap, signal, core_dumped, &mut stdout(), &self.theme);
             term::binary_qestion("Save this snapshot?").unwrap_log()
         };
         if save {
@@ -65,9 +91,10 @@ impl Context {
                 if yes {
                     get_random_name()
                 } else {
-                    let edit_result = editor::open_empty(&self.path, cmd).unwrap_log();
+                    let edit_result = editor::open_empty(&self.path, cmd, &settings).unwrap_log();
                     description = edit_result.description;
                     tags = edit_result.tags;
+                    settings = edit_result.settings;
                     if let Some(name) = edit_result.name {
                         normalize_name(&name)
                     } else {
@@ -75,18 +102,38 @@ impl Context {
                     }
                 }
             };
-            let snapshot = to_snapshot(name, description, tags, cmd.to_owned(), snap);
+            let mut snapshot = to_snapshot(name, description, tags, cmd.to_owned(), snap);
+            settings.apply(&mut snapshot);
             self.data.add_snapshot(snapshot).unwrap_log();
         }
     }
 
     /// Handles run subcommand.
+    /// Each snapshot is run `repeat` times, flaky snapshots can be tagged
+    /// automatically.
     /// Returns true in case of success, false otherwise.
-    pub fn run(&mut self) -> bool {
-        let mut stdout = stdout();
+    pub fn run(&mut self, repeat: usize, tag_flaky: bool) -> bool {
+        if tag_flaky && repeat < 2 {
+            // A single run can not tell flaky snapshots apart
+            Error::from_str::<()>("The --tag-flaky option requires --repeat with at least 2 runs.").unwrap_log();
+        }
+        let mut stdout: Box<dyn Write> = if term::is_interactive() {
+            Box::new(stdout())
+        } else {
+            // Colors and carriage returns would clutter pipes and CI logs
+            Box::new(StripWriter::new(stdout()))
+        };
         let snapshots = self.data.get_all_snapshots().unwrap_log();
         let view = repl::View::new(snapshots);
-        if self.run_view(&view, &mut stdout) {
+        let success = if repeat > 1 {
+            self.repeat_snapshots(view.get_view(), repeat, &mut stdout)
+        } else {
+            self.run_snapshots(view.get_view(), &mut stdout)
+        };
+        if tag_flaky {
+            self.tag_flaky(&view, &mut stdout);
+        }
+        if success {
             term::success(&mut stdout);
             true
         } else {
@@ -95,159 +142,393 @@ impl Context {
         }
     }
 
+    /// Handles diff subcommand.
+    /// Runs the snapshots, or only the one named `name`, and writes the
+    /// changes of their bodies as a unified patch to `output` or stdout.
+    /// Returns true if all snapshots passed, false otherwise.
+    pub fn diff(&mut self, name: &Option<String>, output: &Option<PathBuf>) -> bool {
+        let snapshots = self.get_named_snapshots(name);
+        let mut patch = Vec::new();
+        let mut success = true;
+        for snap in snapshots {
+            let snap = snap.borrow();
+            let result = cmd::execute(&snap.cmd, &self.path).unwrap_log();
+            let comparison = compare(&snap, &result);
+            success = success && comparison.passed();
+            if !comparison.stdout {
+                self.write_patch(&snap, &snap.stdout, "stdout", &result.stdout, &mut patch);
+            }
+            if !comparison.stderr {
+                self.write_patch(&snap, &snap.stderr, "stderr", &result.stderr, &mut patch);
+            }
+        }
+        match output {
+            Some(output) => {
+                wrap(fs::write(output, &patch), "Failed to write the patch.").unwrap_log();
+            }
+            None => stdout().write_all(&patch).unwrap(),
+        }
+        success
+    }
+
+    /// Handles set subcommand.
+    /// Changes the settings given as `(key, value)` options of all snapshots,
+    /// or only of the one named `name`.
+    pub fn set(&mut self, name: &Option<String>, options: &[(&str, &Option<String>)]) {
+        // Check the options before changing any snapshot
+        editor::Settings::from_options(options).unwrap_log();
+        let snapshots = self.get_named_snapshots(name);
+        let mut count = 0;
+        for snap in snapshots {
+            let mut snap = snap.borrow_mut();
+            let mut settings = editor::Settings::of(&snap);
+            settings.update(options).unwrap_log();
+            if settings != editor::Settings::of(&snap) {
+                settings.apply(&mut snap);
+                count += 1;
+            }
+        }
+        if count > 0 {
+            self.data.persist_metadata().unwrap_log();
+        }
+        match count {
+            0 => println!("Nothing to change."),
+            1 => println!("Updated 1 snapshot."),
+            _ => println!("Updated {} snapshots.", count),
+        }
+    }
+
+    /// Handles tag subcommand.
+    /// Adds or removes tags of all snapshots, or only of the one named `name`.
+    pub fn tag(&mut self, name: &Option<String>, changes: &[TagChange]) {
+        let snapshots = self.get_named_snapshots(name);
+        let mut count = 0;
+        for snap in snapshots {
+            if change_tags(&mut snap.borrow_mut(), changes) {
+                count += 1;
+            }
+        }
+        if count > 0 {
+            self.data.persist_metadata().unwrap_log();
+        }
+        println!("{}", format_tagged(count));
+    }
+
     /// Hnadles the exec subcommand.
     pub fn exec(&mut self, commands: &str) {
+        if !term::is_interactive() {
+            let mut view = self.get_view();
+            self.execute_commands(commands, &mut view, &mut term::Plain::new(stdout()));
+            return;
+        }
         let (mut view, mut repl) = self.get_view_and_repl();
         self.execute_commands(commands, &mut view, &mut repl);
     }
 
-    /// Starts the REPL.
+    /// Starts the REPL, or reads commands line by line if the terminal is not
+    /// interactive.
     pub fn repl(&mut self) {
+        if !term::is_interactive() {
+            return self.read_commands();
+        }
         let (mut view, mut repl) = self.get_view_and_repl();
+        view.set_height(repl.list_height());
         loop {
             match repl.run(&view) {
                 Input::Up => view.up(),
                 Input::Down => view.down(),
+                Input::Resize => view.set_height(repl.list_height()),
+                Input::Select(idx) => view.select(idx),
+                Input::Mark => view.toggle_mark(),
                 Input::Quit => break,
-                Input::Command(cmd) => match self.execute_commands(&cmd, &mut view, &mut repl) {
-                    ReplStatus::Exit => break,
-                    ReplStatus::Continue => (),
-                },
+                Input::Command(cmd) => {
+                    self.data.add_history(&cmd).unwrap_log();
+                    match self.execute_commands(&cmd, &mut view, &mut repl) {
+                        ReplStatus::Exit => break,
+                        ReplStatus::Continue => (),
+                    }
+                }
             }
         }
         // Clear the REPL befor exiting
         repl.suspend();
     }
 
+    /// Starts the full-screen interface, or reads commands line by line if
+    /// the terminal is not interactive.
+    pub fn tui(&mut self) {
+        if !term::is_interactive() {
+            return self.read_commands();
+        }
+        let mut view = self.get_view();
+        let mut tui = term::Tui::new(stdin(), stdout());
+        // Side by side diffs do not fit in the detail pane
+        if self.diff_options.layout == Layout::Auto {
+            self.diff_options.layout = Layout::Unified;
+        }
+        view.set_height(tui.list_height());
+        loop {
+            match tui.run(&view) {
+                Input::Up => view.up(),
+                Input::Down => view.down(),
+                Input::Resize => view.set_height(tui.list_height()),
+                Input::Select(idx) => view.select(idx),
+                Input::Mark => view.toggle_mark(),
+                Input::Quit => break,
+                Input::Command(cmd) => {
+                    self.data.add_history(&cmd).unwrap_log();
+                    match self.execute_commands(&cmd, &mut view, &mut tui) {
+                        ReplStatus::Exit => break,
+                        ReplStatus::Continue => (),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Executes the commands read from stdin, one line at a time, and writes
+    /// plain outputs.
+    fn read_commands(&mut self) {
+        let mut view = self.get_view();
+        let mut console = term::Plain::new(stdout());
+        for line in stdin().lock().lines() {
+            let line = wrap(line, "Unable to read from stdin").unwrap_log();
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let ReplStatus::Exit = self.execute_commands(&line, &mut view, &mut console) {
+                break;
+            }
+        }
+    }
+
+    /// Returns all snapshots, or only the one named `name`.
+    fn get_named_snapshots(&mut self, name: &Option<String>) -> Vec<Rc<RefCell<Snapshot>>> {
+        let mut snapshots = self.data.get_all_snapshots().unwrap_log();
+        if let Some(name) = name {
+            snapshots.retain(|snap| &snap.borrow().name == name);
+            if snapshots.is_empty() {
+                Error::from_str::<()>(&format!("No snapshot named {}.", name)).unwrap_log();
+            }
+        }
+        snapshots
+    }
+
+    /// Returns a new View over all snapshots.
+    fn get_view(&mut self) -> View {
+        let snapshots = self.data.get_all_snapshots().unwrap_log();
+        repl::View::new(snapshots)
+    }
+
     /// Returns a new View and Repl.
     fn get_view_and_repl(&mut self) -> (View, term::Repl) {
-        let snapshots = self.data.get_all_snapshots().unwrap_log();
-        let view = repl::View::new(snapshots);
+        let view = self.get_view();
+        let history = self.data.get_history().unwrap_log();
         let stdout = stdout();
         let stdin = stdin();
-        let repl = term::Repl::new(stdin, stdout);
+        let repl = term::Repl::new(stdin, stdout, history);
         (view, repl)
     }
 
     /// Parses and executes commands.
-    fn execute_commands(&mut self, commands: &str, view: &mut View, repl: &mut term::Repl) -> ReplStatus {
+    fn execute_commands<C: Console>(&mut self, commands: &str, view: &mut View, console: &mut C) -> ReplStatus {
         let commands = match parse(commands) {
             Ok(commands) => commands,
             Err(error) => {
-                repl.suspend();
-                repl.writeln(&error);
+                console.suspend();
+                for line in error.lines() {
+                    console.writeln(line);
+                }
                 Vec::new()
             }
         };
         for command in commands {
             match command {
                 Command::Quit => return ReplStatus::Exit,
-                Command::Help => self.execute_help(repl),
-                Command::Edit => self.execute_edit(repl, view),
+                Command::Help => self.execute_help(console),
+                Command::Edit => self.execute_edit(console, view),
                 Command::Clear => view.clear_filters(),
                 Command::Filter(args) => view.apply_filter(args),
-                Command::Run(target) => self.execute_run(repl, view, target),
-                Command::Show(target) => self.execute_show(repl, view, target),
-                Command::Update(target) => self.execute_update(repl, view, target),
-                Command::Delete(target) => self.execute_delete(repl, view, target),
+                Command::Mark => view.mark_all(true),
+                Command::Unmark => view.mark_all(false),
+                Command::Pop(position) => {
+                    if !view.pop_filter(position) {
+                        console.suspend();
+                        console.writeln("No filter to remove.");
+                    }
+                }
+                Command::Run(target) => self.execute_run(console, view, target),
+                Command::Repeat(repeat, target) => self.execute_repeat(console, view, repeat, target),
+                Command::Show(target) => self.execute_show(console, view, target),
+                Command::Update(target) => self.execute_update(console, view, target),
+                Command::Delete(target) => self.execute_delete(console, view, target),
+                Command::Tag(changes, target) => self.execute_tag(console, view, &changes, target),
+                Command::Tags => self.execute_tags(console, view),
             }
         }
         ReplStatus::Continue
     }
 
     /// Executes the help command.
-    fn execute_help(&self, repl: &mut term::Repl) {
-        term::help::write_help(&mut repl.stdout);
+    fn execute_help<C: Console>(&self, console: &mut C) {
+        term::help::write_help(&mut console.output());
     }
 
     /// Executes the edit command.
-    fn execute_edit(&self, repl: &mut term::Repl, view: &View) {
-        repl.suspend();
+    fn execute_edit<C: Console>(&self, console: &mut C, view: &View) {
+        console.suspend();
         if let Some(mut snap) = view.get_selected_mut() {
-            if self.edit_snapshot(&mut snap, &mut repl.stdout) {
+            if self.edit_snapshot(&mut snap, &mut console.output()) {
                 drop(snap); // Release the mutable borrow to allow data.persist
                 self.data.persist_metadata().unwrap_log();
             }
         } else {
-            repl.writeln("No snapshot to edit.")
+            console.writeln("No snapshot to edit.")
         }
     }
 
-    /// Executes the run command.
-    fn execute_run(&mut self, repl: &mut term::Repl, view: &View, target: Target) {
-        repl.suspend();
+    /// Executes the run command, results are written as snapshots complete
+    /// and can be read again once the run is over.
+    fn execute_run<C: Console>(&mut self, console: &mut C, view: &View, target: Target) {
+        console.suspend();
+        let mut output = Recorder::new(console.output());
+        let success = match target {
+            Target::All => self.run_snapshots(view.get_view(), &mut output),
+            Target::Marked => self.run_snapshots(&view.get_marked(), &mut output),
+            Target::Selected => match view.get_selected_mut() {
+                Some(mut snap) => self.run_snapshot(&mut snap, &mut output),
+                None => true,
+            },
+        };
+        if success {
+            term::success(&mut output);
+        } else {
+            term::failure(&mut output);
+        }
+        let recorded = output.recorded;
+        console.review(&recorded);
+    }
+
+    /// Executes the repeat command.
+    fn execute_repeat<C: Console>(&mut self, console: &mut C, view: &View, repeat: usize, target: Target) {
+        console.suspend();
         let success = match target {
-            Target::All => self.run_view(&view, &mut repl.stdout),
+            Target::All => self.repeat_snapshots(view.get_view(), repeat, &mut console.output()),
+            Target::Marked => self.repeat_snapshots(&view.get_marked(), repeat, &mut console.output()),
             Target::Selected => match view.get_selected_mut() {
-                Some(mut snap) => self.run_snapshot(&mut snap, &mut repl.stdout),
+                Some(mut snap) => self.repeat_snapshot(&mut snap, repeat, &mut console.output()) == SnapshotStatus::Passed,
                 None => true,
             },
         };
         if success {
-            term::success(&mut repl.stdout);
+            term::success(&mut console.output());
         } else {
-            term::failure(&mut repl.stdout);
+            term::failure(&mut console.output());
         }
     }
 
     /// Executes the run command.
-    fn execute_update(&mut self, repl: &mut term::Repl, view: &View, target: Target) {
-        repl.suspend();
+    fn execute_update<C: Console>(&mut self, console: &mut C, view: &View, target: Target) {
+        console.suspend();
         match target {
-            Target::All => self.update_view(repl, view),
-            Target::Selected => self.update_selected(repl, view),
+            Target::All => self.update_snapshots(console, view.get_view()),
+            Target::Marked => self.update_snapshots(console, &view.get_marked()),
+            Target::Selected => self.update_selected(console, view),
         };
     }
 
     /// Executes the show command.
-    fn execute_show(&self, repl: &mut term::Repl, view: &View, target: Target) {
-        repl.suspend();
+    fn execute_show<C: Console>(&self, console: &mut C, view: &View, target: Target) {
+        console.suspend();
+        let mut output = Vec::new();
         match target {
             Target::Selected => match view.get_selected() {
-                Some(snap) => self.show_snapshot(&snap, &mut repl.stdout),
+                Some(snap) => self.show_snapshot(&snap, &mut output),
                 None => (),
             },
             Target::All => {
                 for snap in view.get_view() {
-                    self.show_snapshot(&snap.borrow(), &mut repl.stdout);
+                    self.show_snapshot(&snap.borrow(), &mut output);
+                }
+            }
+            Target::Marked => {
+                for snap in view.get_marked() {
+                    self.show_snapshot(&snap.borrow(), &mut output);
                 }
             }
         }
+        console.page(&output);
     }
 
     /// Executes the delete command.
-    fn execute_delete(&self, repl: &mut term::Repl, view: &mut View, target: Target) {
-        repl.suspend();
+    fn execute_delete<C: Console>(&self, console: &mut C, view: &mut View, target: Target) {
+        console.suspend();
         match target {
             Target::Selected => match view.get_selected_mut() {
                 Some(mut snap) => {
                     snap.deleted = true;
-                    repl.writeln("Deleted 1 snapshot");
+                    console.writeln("Deleted 1 snapshot");
                 }
-                None => repl.writeln("No snapshot to delete."),
+                None => console.writeln("No snapshot to delete."),
             },
-            Target::All => {
+            Target::All | Target::Marked => {
+                let snapshots = match target {
+                    Target::Marked => view.get_marked(),
+                    _ => view.get_view().clone(),
+                };
                 let mut count = 0;
-                for snap in view.get_view() {
+                for snap in snapshots {
                     let mut snap = snap.borrow_mut();
                     snap.deleted = true;
                     count += 1;
                 }
                 if count > 1 {
-                    repl.writeln(&format!("Deleted {} snapshots.", count));
+                    console.writeln(&format!("Deleted {} snapshots.", count));
                 } else {
-                    repl.writeln(&format!("Deleted {} snapshot.", count));
+                    console.writeln(&format!("Deleted {} snapshot.", count));
                 }
             }
         }
         self.data.gc_snapshots().unwrap_log();
-        view.apply_filter(Filter::Deleted);
+        view.refresh();
     }
 
-    /// Runs only commands from the given view.
-    fn run_view<B: Write>(&mut self, view: &View, buffer: &mut B) -> bool {
+    /// Executes the tag command.
+    fn execute_tag<C: Console>(&self, console: &mut C, view: &mut View, changes: &[TagChange], target: Target) {
+        console.suspend();
+        let snapshots = match target {
+            Target::All => view.get_view().clone(),
+            Target::Marked => view.get_marked(),
+            Target::Selected => match view.get_view().get(view.get_selected_index()) {
+                Some(snap) => vec![Rc::clone(snap)],
+                None => return console.writeln("No snapshot to tag."),
+            },
+        };
+        let mut count = 0;
+        for snap in snapshots {
+            if change_tags(&mut snap.borrow_mut(), changes) {
+                count += 1;
+            }
+        }
+        if count > 0 {
+            self.data.persist_metadata().unwrap_log();
+        }
+        console.writeln(&format_tagged(count));
+        view.refresh();
+    }
+
+    /// Executes the tags command.
+    fn execute_tags<C: Console>(&self, console: &mut C, view: &View) {
+        console.suspend();
+        let mut output = Vec::new();
+        term::tag_stats(&view.get_tag_stats(), &mut output);
+        console.page(&output);
+    }
+
+    /// Runs the given snapshots.
+    fn run_snapshots<B: Write>(&mut self, snapshots: &[Rc<RefCell<Snapshot>>], buffer: &mut B) -> bool {
         let mut success = true;
-        for snap in view.get_view() {
+        for snap in snapshots {
             let pass = self.run_snapshot(&mut snap.borrow_mut(), buffer);
             success = success && pass;
         }
@@ -257,49 +538,135 @@ impl Context {
     /// Runs a single snapshot.
     fn run_snapshot<B: Write>(&self, snap: &mut Snapshot, buffer: &mut B) -> bool {
         let theme = &self.theme;
-        let empty_body = Vec::new();
         let result = cmd::execute(&snap.cmd, &self.path).unwrap_log();
-        let old_stdout = if let Some(ref stdout) = snap.stdout {
-            &stdout.body
-        } else {
-            &empty_body
-        };
-        let old_stderr = if let Some(ref stderr) = snap.stderr {
-            &stderr.body
-        } else {
-            &empty_body
-        };
-        let stdout_eq = &result.stdout == old_stdout;
-        let stderr_eq = &result.stderr == old_stderr;
-        let code_eq = snap.exit_code == result.status.code();
-        let failed = !stdout_eq || !stderr_eq || !code_eq;
+        let comparison = compare(snap, &result);
+        let failed = !comparison.passed();
         // Draw test summary
         if failed {
+            let mut diff = Vec::new();
+            self.write_differences(snap, &result, &comparison, &mut diff);
             term::box_separator(&snap.name, SeparatorKind::Top, buffer, theme);
-            term::snap_summary(snap.description.as_ref(), &snap.cmd, snap.exit_code, buffer, theme);
+            term::snap_summary(snap, buffer, theme);
+            buffer.write_all(&diff).unwrap();
+            term::box_separator("", SeparatorKind::Bottom, buffer, theme);
+            snap.status = SnapshotStatus::Failed;
+            snap.diff = Some(diff);
+        } else {
+            snap.status = SnapshotStatus::Passed;
+            snap.diff = None;
         }
-        if &result.stdout != old_stdout {
+        !failed
+    }
+
+    /// Runs the given snapshots `repeat` times each, then writes down how
+    /// many snapshots are stable and how many are flaky.
+    /// Returns true if all snapshots passed on every run.
+    fn repeat_snapshots<B: Write>(&mut self, snapshots: &[Rc<RefCell<Snapshot>>], repeat: usize, buffer: &mut B) -> bool {
+        let (mut passed, mut failed, mut flaky) = (0, 0, 0);
+        for snap in snapshots {
+            match self.repeat_snapshot(&mut snap.borrow_mut(), repeat, buffer) {
+                SnapshotStatus::Passed => passed += 1,
+                SnapshotStatus::Flaky => flaky += 1,
+                _ => failed += 1,
+            }
+        }
+        term::repeat_summary(passed, failed, flaky, buffer);
+        failed == 0 && flaky == 0
+    }
+
+    /// Runs a single snapshot `repeat` times and classifies it.
+    /// A snapshot is flaky if some runs passed and others failed, the runs are
+    /// drawn grouped by outcome when they differ.
+    fn repeat_snapshot<B: Write>(&self, snap: &mut Snapshot, repeat: usize, buffer: &mut B) -> SnapshotStatus {
+        let theme = &self.theme;
+        let results = (0..repeat).map(|_| cmd::execute(&snap.cmd, &self.path).unwrap_log()).collect();
+        let outcomes = group_runs(snap, results);
+        let status = repeat_status(&outcomes);
+
+        // Draw test summary
+        if status != SnapshotStatus::Passed {
+            term::box_separator(&snap.name, SeparatorKind::Top, buffer, theme);
+            term::snap_summary(snap, buffer, theme);
+            for outcome in &outcomes {
+                if outcomes.len() > 1 {
+                    let verdict = if outcome.passed { "passed" } else { "failed" };
+                    term::box_separator(&format_runs(&outcome.runs, verdict), SeparatorKind::Middle, buffer, theme);
+                }
+                let comparison = compare(snap, &outcome.result);
+                self.write_differences(snap, &outcome.result, &comparison, buffer);
+            }
+            term::box_separator("", SeparatorKind::Bottom, buffer, theme);
+        }
+        snap.status = status;
+        status
+    }
+
+    /// Draws the status code, stdout and stderr differences between a snapshot
+    /// and an execution result.
+    fn write_differences<B: Write>(&self, snap: &Snapshot, result: &Output, comparison: &Comparison, buffer: &mut B) {
+        let theme = &self.theme;
+        if !comparison.code || !comparison.signal {
+            let (signal, core_dumped) = cmd::get_signal(&result.status);
+            let code = term::format_status(result.status.code(), signal, core_dumped);
+            buffer.boxed_write_str(&format!("got code: {}", code), theme).unwrap();
+        }
+        if !comparison.stdout {
             term::box_separator("stdout", SeparatorKind::Middle, buffer, theme);
-            term::write_diff(old_stdout, &result.stdout, buffer, theme);
+            let (old, new) = (snap.prepare(get_body(&snap.stdout)), snap.prepare(&result.stdout));
+            term::write_stream_diff(&snap.stdout_mode, &old, &new, &self.diff_options, buffer, theme);
         }
-        if &result.stderr != old_stderr {
+        if !comparison.stderr {
             term::box_separator("stderr", SeparatorKind::Middle, buffer, theme);
-            term::write_diff(old_stderr, &result.stderr, buffer, theme);
+            let (old, new) = (snap.prepare(get_body(&snap.stderr)), snap.prepare(&result.stderr));
+            term::write_stream_diff(&snap.stderr_mode, &old, &new, &self.diff_options, buffer, theme);
         }
-        if failed {
-            term::box_separator("", SeparatorKind::Bottom, buffer, theme);
-            snap.status = SnapshotStatus::Failed;
+    }
+
+    /// Writes the patch turning a snapshot body into a new output. The path
+    /// of the snapshot file is given relative to the project directory.
+    /// Bodies without a file are reported instead, as a patch creating it
+    /// would leave the metadata without a reference to it.
+    fn write_patch<B: Write>(&self, snap: &Snapshot, data: &Option<SnapshotData>, stream: &str, new: &[u8], buffer: &mut B) {
+        let data = match data {
+            Some(data) => data,
+            None => {
+                eprintln!("The {} of {} has no snapshot file to patch, update it from the REPL.", stream, snap.name);
+                return;
+            }
+        };
+        let path = self.data.get_snapshot_path(&data.path);
+        let path = path.strip_prefix(&self.path).unwrap_or(&path);
+        let options = &self.diff_options;
+        let path = path.to_string_lossy();
+        diff::write_patch(&path, &data.body, new, options.algorithm, options.context, buffer).unwrap();
+    }
+
+    /// Tags the flaky snapshots of the view with `#flaky`, then persists
+    /// metadata.
+    fn tag_flaky<B: Write>(&self, view: &View, buffer: &mut B) {
+        let mut count = 0;
+        let changes = [TagChange::Add(FLAKY_TAG.to_owned())];
+        for snap in view.get_view() {
+            let mut snap = snap.borrow_mut();
+            if snap.status == SnapshotStatus::Flaky && change_tags(&mut snap, &changes) {
+                count += 1;
+            }
+        }
+        if count > 0 {
+            self.data.persist_metadata().unwrap_log();
+        }
+        if count == 1 {
+            term::writeln("Tagged 1 snapshot as flaky.", buffer);
         } else {
-            snap.status = SnapshotStatus::Passed;
+            term::writeln(&format!("Tagged {} snapshots as flaky.", count), buffer);
         }
-        !failed
     }
 
     /// Shows a single test.
     fn show_snapshot<B: Write>(&self, snap: &Snapshot, buffer: &mut B) {
         let theme = &self.theme;
         term::box_separator(&snap.name, SeparatorKind::Top, buffer, theme);
-        term::snap_summary(snap.description.as_ref(), &snap.cmd, snap.exit_code, buffer, theme);
+        term::snap_summary(snap, buffer, theme);
         if let Some(stdout) = &snap.stdout {
             term::box_separator("stdout", SeparatorKind::Middle, buffer, theme);
             buffer.boxed_write(&stdout.body, theme).unwrap();
@@ -314,11 +681,7 @@ impl Context {
     /// Edits the selected snapshot.
     /// Returns true if there was a change, false otherwise.
     fn edit_snapshot<B: Write>(&self, snap: &mut Snapshot, buffer: &mut B) -> bool {
-        let description = match snap.description.as_ref() {
-            Some(desc) => desc,
-            None => "",
-        };
-        match editor::open_snap(&self.path, &snap.name, description, &snap.cmd) {
+        match editor::open_snap(&self.path, snap) {
             Ok(edit) => {
                 let mut has_changed = false;
                 if let Some(name) = edit.name {
@@ -327,8 +690,15 @@ impl Context {
                         has_changed = true;
                     }
                 }
+                if edit.settings != editor::Settings::of(snap) {
+                    edit.settings.apply(snap);
+                    has_changed = true;
+                }
                 if edit.description != snap.description {
                     snap.description = edit.description;
+                    has_changed = true;
+                }
+                if edit.tags != snap.tags {
                     snap.tags = edit.tags;
                     has_changed = true;
                 }
@@ -347,10 +717,10 @@ impl Context {
         }
     }
 
-    /// Updates all the snapshots of the current view.
-    fn update_view(&self, repl: &mut term::Repl, view: &View) {
+    /// Updates the given snapshots.
+    fn update_snapshots<C: Console>(&self, console: &mut C, snapshots: &[Rc<RefCell<Snapshot>>]) {
         let mut count = 0;
-        for snap in view.get_view() {
+        for snap in snapshots {
             let mut snap = snap.borrow_mut();
             if self.update_snapshot(&mut snap) {
                 self.data.persist_snapshot_data(&snap).unwrap_log();
@@ -359,30 +729,30 @@ impl Context {
         }
         if count > 0 {
             if count == 1 {
-                repl.writeln("Updated 1 snapshot.");
+                console.writeln("Updated 1 snapshot.");
             } else {
-                repl.writeln(&format!("Updated {} snapshots.", count));
+                console.writeln(&format!("Updated {} snapshots.", count));
             }
             self.data.persist_metadata().unwrap_log();
         } else {
-            repl.writeln("Nothing to do.");
+            console.writeln("Nothing to do.");
         }
     }
 
     /// Updates the snapshot selected in the current view.
-    fn update_selected(&self, repl: &mut term::Repl, view: &View) {
+    fn update_selected<C: Console>(&self, console: &mut C, view: &View) {
         match view.get_selected_mut() {
             Some(mut snap) => {
                 if self.update_snapshot(&mut snap) {
                     self.data.persist_snapshot_data(&snap).unwrap_log();
                     drop(snap); // Release mut ref before persisting
                     self.data.persist_metadata().unwrap_log();
-                    repl.writeln("Updated 1 snapshot.")
+                    console.writeln("Updated 1 snapshot.")
                 } else {
-                    repl.writeln("Nothing to do.")
+                    console.writeln("Nothing to do.")
                 }
             }
-            None => repl.writeln("No snapshot to update."),
+            None => console.writeln("No snapshot to update."),
         }
     }
 
@@ -399,6 +769,12 @@ impl Context {
             snap.exit_code = result.status.code();
             has_changed = true;
         }
+        let (signal, core_dumped) = cmd::get_signal(&result.status);
+        if snap.signal != signal || snap.core_dumped != core_dumped {
+            snap.signal = signal;
+            snap.core_dumped = core_dumped;
+            has_changed = true;
+        }
         if snap.stdout != new_stdout {
             snap.stdout = new_stdout;
             has_changed = true;
@@ -411,3 +787,46 @@ impl Context {
         has_changed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn test_diff_patch() {
+        let root = std::env::temp_dir().join(format!("parrot-diff-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let write = |file: &str, body: &str| fs::write(root.join(file), body).unwrap();
+        let diff = |patch: &str| {
+            let mut context = Context::new(root.clone(), 3, None, None).ok().unwrap();
+            let passed = context.diff(&None, &Some(root.join(patch)));
+            (passed, fs::read_to_string(root.join(patch)).unwrap())
+        };
+        write("out", "hi\n");
+        write("err", "");
+        let mut context = Context::new(root.clone(), 3, None, None).ok().unwrap();
+        context.init();
+        context.add("cat out; cat err >&2", &Some(String::from("s")), editor::Settings::default(), true);
+
+        // The stdout file is emptied, the stderr one does not exist
+        write("out", "");
+        write("err", "oops\n");
+        let (passed, patch) = diff("1.patch");
+        assert!(!passed);
+        assert!(patch.starts_with("--- a/.parrot/snapshots/s.out"));
+        assert!(!patch.contains("/dev/null") && !patch.contains(".err"));
+        let status = process::Command::new("git")
+            .args(["apply", "1.patch"])
+            .current_dir(&root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        // The snapshot passes once the patch is applied
+        write("err", "");
+        assert_eq!(diff("2.patch"), (true, String::new()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
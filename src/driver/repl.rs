@@ -1,13 +1,25 @@
 use std::cell::{Ref, RefCell, RefMut};
 use std::rc::Rc;
 
-use crate::parser::Filter;
+use crate::parser::{Comparison, Field, Filter, Pattern};
 use crate::data::{Snapshot, SnapshotStatus};
 
+/// The number of snapshots with a tag, by status.
+#[derive(Default)]
+pub struct TagStats {
+    pub tag: String,
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub flaky: usize,
+}
+
 /// Represents a view of the snapshots after filters have been applied.
 pub struct View {
     data: Vec<Rc<RefCell<Snapshot>>>,
     view: Vec<Rc<RefCell<Snapshot>>>,
+    /// Filters applied to the data, from the first one
+    filters: Vec<Filter>,
     /// Height of the view window
     pub height: usize,
     /// Position of the cursor relative to the view window
@@ -27,6 +39,7 @@ impl View {
         View {
             data,
             view,
+            filters: Vec::new(),
             window: (0, std::cmp::min(height, n)),
             height,
             cursor: 0,
@@ -58,6 +71,113 @@ impl View {
         }
     }
 
+    /// Returns the marked snapshots of the view.
+    pub fn get_marked(&self) -> Vec<Rc<RefCell<Snapshot>>> {
+        self.view.iter().filter(|snap| snap.borrow().marked).map(Rc::clone).collect()
+    }
+
+    /// Marks the selected snapshot, or unmarks it if already marked.
+    pub fn toggle_mark(&mut self) {
+        if let Some(mut snap) = self.get_selected_mut() {
+            snap.marked = !snap.marked;
+        }
+    }
+
+    /// Marks or unmarks all snapshots of the view.
+    pub fn mark_all(&mut self, marked: bool) {
+        for snap in &self.view {
+            snap.borrow_mut().marked = marked;
+        }
+    }
+
+    /// Returns the names of all snapshots, regardless of filters.
+    pub fn get_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .data
+            .iter()
+            .filter(|snap| !snap.borrow().deleted)
+            .map(|snap| snap.borrow().name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Returns the tags of all snapshots, regardless of filters.
+    pub fn get_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .data
+            .iter()
+            .filter(|snap| !snap.borrow().deleted)
+            .flat_map(|snap| snap.borrow().tags.clone())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Returns the statistics of every tag, regardless of filters, sorted by
+    /// tag.
+    pub fn get_tag_stats(&self) -> Vec<TagStats> {
+        let mut stats: Vec<TagStats> = Vec::new();
+        for snap in &self.data {
+            let snap = snap.borrow();
+            if snap.deleted {
+                continue;
+            }
+            for tag in &snap.tags {
+                let idx = match stats.binary_search_by(|stats| stats.tag.cmp(tag)) {
+                    Ok(idx) => idx,
+                    Err(idx) => {
+                        let tag = tag.clone();
+                        stats.insert(idx, TagStats { tag, ..TagStats::default() });
+                        idx
+                    }
+                };
+                let stats = &mut stats[idx];
+                stats.total += 1;
+                match snap.status {
+                    SnapshotStatus::Passed => stats.passed += 1,
+                    SnapshotStatus::Failed => stats.failed += 1,
+                    SnapshotStatus::Flaky => stats.flaky += 1,
+                    SnapshotStatus::Waiting => (),
+                }
+            }
+        }
+        stats
+    }
+
+    /// Changes the height of the window, the selected snapshot is kept in the
+    /// window.
+    pub fn set_height(&mut self, height: usize) {
+        let selected = self.get_selected_index();
+        self.height = height;
+        self.select(selected);
+    }
+
+    /// Returns the index of the selected snapshot in the view.
+    pub fn get_selected_index(&self) -> usize {
+        self.window.0 + self.cursor
+    }
+
+    /// Selects the snapshot at the given index of the view, the window is
+    /// moved as little as possible to contain it.
+    pub fn select(&mut self, idx: usize) {
+        let n = self.view.len();
+        let selected = std::cmp::min(idx, n.saturating_sub(1));
+        let mut min = self.window.0;
+        if selected < min {
+            min = selected;
+        } else if selected >= min + self.height {
+            min = selected + 1 - self.height;
+        }
+        // Fill the window if there are enough snapshots
+        min = std::cmp::min(min, n.saturating_sub(self.height));
+        let max = std::cmp::min(min + self.height, n);
+        self.window = (min, max);
+        self.cursor = selected.saturating_sub(min);
+    }
+
     /// Moves the cursor up.
     pub fn up(&mut self) {
         let (min, _) = self.window;
@@ -99,28 +219,50 @@ impl View {
         }
     }
 
-    /// Applies a filter
+    /// Narrows the view with a filter, which is pushed on the filter stack.
     pub fn apply_filter(&mut self, filter: Filter) {
-        match filter {
-            Filter::Tag(ref tag) => self.apply_tag_filter(tag),
-            Filter::Name(ref name) => self.apply_name_filter(name),
-            Filter::Waiting => self.apply_status_filter(SnapshotStatus::Waiting),
-            Filter::Passed => self.apply_status_filter(SnapshotStatus::Passed),
-            Filter::Failed => self.apply_status_filter(SnapshotStatus::Failed),
-            Filter::Deleted => self.apply_deleted_filter(),
-        }
+        self.view.retain(|snap| matches(&filter, &snap.borrow()));
+        self.filters.push(filter);
         self.update_window();
     }
 
     /// Remove any filter currently applied.
     pub fn clear_filters(&mut self) {
-        let mut view = Vec::with_capacity(self.data.len());
-        for snap in &self.data {
-            if snap.borrow().deleted == false {
-                view.push(Rc::clone(snap));
-            }
-        }
-        self.view = view;
+        self.filters.clear();
+        self.refresh();
+    }
+
+    /// Removes the filter at the given position of the stack, starting at 1,
+    /// or the latest filter. Returns false if there is no such filter.
+    pub fn pop_filter(&mut self, position: Option<usize>) -> bool {
+        let idx = match position {
+            Some(position) if position <= self.filters.len() => position - 1,
+            None if !self.filters.is_empty() => self.filters.len() - 1,
+            _ => return false,
+        };
+        self.filters.remove(idx);
+        self.refresh();
+        true
+    }
+
+    /// Returns the filter stack, from the first filter applied.
+    pub fn get_filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// Rebuilds the view from the snapshots that are not deleted and match
+    /// every filter of the stack.
+    pub fn refresh(&mut self) {
+        let filters = &self.filters;
+        self.view = self
+            .data
+            .iter()
+            .filter(|snap| {
+                let snap = snap.borrow();
+                !snap.deleted && filters.iter().all(|filter| matches(filter, &snap))
+            })
+            .map(Rc::clone)
+            .collect();
         self.update_window();
     }
 
@@ -145,44 +287,51 @@ impl View {
             self.window = (min, max);
         }
     }
+}
 
-    /// Applies a tag filter.
-    fn apply_tag_filter(&mut self, tag: &String) {
-        let old_view = std::mem::replace(&mut self.view, Vec::new());
-        for snap in old_view {
-            if snap.borrow().tags.contains(tag) {
-                self.view.push(snap);
-            }
-        }
-    }
-
-    /// Applies a name filter.
-    fn apply_name_filter(&mut self, name: &String) {
-        let old_view = std::mem::replace(&mut self.view, Vec::new());
-        for snap in old_view {
-            if snap.borrow().name.contains(name) {
-                self.view.push(snap);
-            }
-        }
-    }
-
-    /// Applies a status filter.
-    fn apply_status_filter(&mut self, status: SnapshotStatus) {
-        let old_view = std::mem::replace(&mut self.view, Vec::new());
-        for snap in old_view {
-            if snap.borrow().status == status {
-                self.view.push(snap);
-            }
+/// Returns true if the snapshot matches the filter.
+fn matches(filter: &Filter, snap: &Snapshot) -> bool {
+    match filter {
+        Filter::Tag(tag) => snap.tags.contains(tag),
+        Filter::Name(name) => snap.name.contains(name),
+        Filter::Waiting => snap.status == SnapshotStatus::Waiting,
+        Filter::Passed => snap.status == SnapshotStatus::Passed,
+        Filter::Failed => snap.status == SnapshotStatus::Failed,
+        Filter::Flaky => snap.status == SnapshotStatus::Flaky,
+        Filter::Field(field, pattern) => {
+            let text = match field {
+                Field::Name => snap.name.as_bytes(),
+                Field::Cmd => snap.cmd.as_bytes(),
+                Field::Description => snap.description.as_deref().unwrap_or("").as_bytes(),
+                Field::Stdout => snap.stdout.as_ref().map_or(&[][..], |data| &data.body),
+                Field::Stderr => snap.stderr.as_ref().map_or(&[][..], |data| &data.body),
+            };
+            matches_pattern(pattern, text)
         }
+        Filter::Code(comparison, expected) => match snap.exit_code {
+            Some(code) => match comparison {
+                Comparison::Equal => code == *expected,
+                Comparison::NotEqual => code != *expected,
+                Comparison::Less => code < *expected,
+                Comparison::LessOrEqual => code <= *expected,
+                Comparison::Greater => code > *expected,
+                Comparison::GreaterOrEqual => code >= *expected,
+            },
+            // Terminated by a signal
+            None => *comparison == Comparison::NotEqual,
+        },
+        Filter::And(left, right) => matches(left, snap) && matches(right, snap),
+        Filter::Or(left, right) => matches(left, snap) || matches(right, snap),
+        Filter::Not(filter) => !matches(filter, snap),
     }
+}
 
-    /// Applies the deleted filter.
-    fn apply_deleted_filter(&mut self) {
-        let old_view = std::mem::replace(&mut self.view, Vec::new());
-        for snap in old_view {
-            if snap.borrow().deleted == false {
-                self.view.push(snap);
-            }
+/// Returns true if the text matches the pattern.
+fn matches_pattern(pattern: &Pattern, text: &[u8]) -> bool {
+    match pattern {
+        Pattern::Contains(needle) => {
+            needle.is_empty() || text.windows(needle.len()).any(|window| window == needle.as_bytes())
         }
+        Pattern::Regex(matcher) | Pattern::Glob(matcher) => matcher.is_match(text),
     }
 }
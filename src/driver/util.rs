@@ -1,8 +1,130 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use std::io;
+use std::io::Write;
 use std::process::Output;
 
-use crate::data::{Snapshot, SnapshotData, SnapshotStatus};
+use super::cmd::get_signal;
+use crate::data::{sorted_lines, AnsiMode, ExpectedCode, Snapshot, SnapshotData, SnapshotStatus, StreamMode, Whitespace};
+use crate::parser::TagChange;
+
+/// The outcome of comparing an execution result against a snapshot.
+pub struct Comparison {
+    pub code: bool,
+    pub signal: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+impl Comparison {
+    /// Returns true if the execution result matches the snapshot.
+    pub fn passed(&self) -> bool {
+        self.code && self.signal && self.stdout && self.stderr
+    }
+}
+
+/// The runs of a snapshot that produced the same outcome.
+pub struct Outcome {
+    /// The result of the first of these runs.
+    pub result: Output,
+    pub passed: bool,
+    pub runs: Vec<usize>,
+}
+
+/// A writer that keeps a copy of everything written to its inner writer.
+pub struct Recorder<W: Write> {
+    inner: W,
+    pub recorded: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(inner: W) -> Recorder<W> {
+        Recorder {
+            inner,
+            recorded: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Recorder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.recorded.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Compares an execution result against a snapshot.
+pub fn compare(snap: &Snapshot, result: &Output) -> Comparison {
+    Comparison {
+        code: snap.expected_code.matches(snap.exit_code, result.status.code()),
+        signal: (snap.signal, snap.core_dumped) == get_signal(&result.status),
+        stdout: snap.stdout_mode.matches(&snap.prepare(get_body(&snap.stdout)), &snap.prepare(&result.stdout)),
+        stderr: snap.stderr_mode.matches(&snap.prepare(get_body(&snap.stderr)), &snap.prepare(&result.stderr)),
+    }
+}
+
+/// Groups the results of repeated runs by outcome, in order of first
+/// appearance. Results with the same verdict, that only differ in what the
+/// snapshot settings ignore, are the same outcome.
+pub fn group_runs(snap: &Snapshot, results: Vec<Output>) -> Vec<Outcome> {
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    for (idx, result) in results.into_iter().enumerate() {
+        let passed = compare(snap, &result).passed();
+        match outcomes
+            .iter_mut()
+            .find(|outcome| outcome.passed == passed && same_outcome(snap, &outcome.result, &result))
+        {
+            Some(outcome) => outcome.runs.push(idx + 1),
+            None => outcomes.push(Outcome {
+                result,
+                passed,
+                runs: vec![idx + 1],
+            }),
+        }
+    }
+    outcomes
+}
+
+/// Classifies a snapshot by the verdicts of its runs: passed or failed if
+/// they all agree, flaky otherwise.
+pub fn repeat_status(outcomes: &[Outcome]) -> SnapshotStatus {
+    if outcomes.iter().all(|outcome| outcome.passed) {
+        SnapshotStatus::Passed
+    } else if outcomes.iter().all(|outcome| !outcome.passed) {
+        SnapshotStatus::Failed
+    } else {
+        SnapshotStatus::Flaky
+    }
+}
+
+/// Returns true if two execution results of a snapshot are the same once
+/// prepared for comparison, ignored streams and expected exit codes aside.
+fn same_outcome(snap: &Snapshot, a: &Output, b: &Output) -> bool {
+    let expected = |result: &Output| snap.expected_code.matches(snap.exit_code, result.status.code());
+    let same_code = a.status.code() == b.status.code() || (expected(a) && expected(b));
+    let same_stream = |mode: &StreamMode, a: &[u8], b: &[u8]| match mode {
+        StreamMode::Ignore => true,
+        StreamMode::Lines => sorted_lines(&snap.prepare(a)) == sorted_lines(&snap.prepare(b)),
+        _ => snap.prepare(a) == snap.prepare(b),
+    };
+    same_code
+        && get_signal(&a.status) == get_signal(&b.status)
+        && same_stream(&snap.stdout_mode, &a.stdout, &b.stdout)
+        && same_stream(&snap.stderr_mode, &a.stderr, &b.stderr)
+}
+
+/// Returns the body of a snapshot data, or an empty body if there is none.
+pub fn get_body(data: &Option<SnapshotData>) -> &[u8] {
+    match data {
+        Some(data) => &data.body,
+        None => &[],
+    }
+}
 
 /// Creates a snapshot out of an execution result
 pub fn to_snapshot(
@@ -13,6 +135,7 @@ pub fn to_snapshot(
     snap: Output,
 ) -> Snapshot {
     let exit_code = snap.status.code();
+    let (signal, core_dumped) = get_signal(&snap.status);
     let stdout = to_snapshot_data(snap.stdout, &name, ".out");
     let stderr = to_snapshot_data(snap.stderr, &name, ".err");
     Snapshot {
@@ -21,9 +144,18 @@ pub fn to_snapshot(
         description,
         tags,
         exit_code,
+        expected_code: ExpectedCode::Exact,
+        stdout_mode: StreamMode::Exact,
+        stderr_mode: StreamMode::Exact,
+        ansi: AnsiMode::Keep,
+        whitespace: Whitespace::default(),
+        signal,
+        core_dumped,
         stdout,
         stderr,
         status: SnapshotStatus::Waiting,
+        diff: None,
+        marked: false,
         deleted: false,
     }
 }
@@ -39,6 +171,37 @@ pub fn to_snapshot_data(body: Vec<u8>, path: &str, path_extension: &str) -> Opti
     }
 }
 
+/// Adds or removes tags of a snapshot.
+/// Returns true if there was a change, false otherwise.
+pub fn change_tags(snap: &mut Snapshot, changes: &[TagChange]) -> bool {
+    let mut has_changed = false;
+    for change in changes {
+        match change {
+            TagChange::Add(tag) if !snap.tags.contains(tag) => snap.tags.push(tag.clone()),
+            TagChange::Remove(tag) if snap.tags.contains(tag) => snap.tags.retain(|t| t != tag),
+            _ => continue,
+        }
+        has_changed = true;
+    }
+    has_changed
+}
+
+/// Formats the number of tagged snapshots, for instance "Tagged 2 snapshots.".
+pub fn format_tagged(count: usize) -> String {
+    match count {
+        0 => String::from("Nothing to change."),
+        1 => String::from("Tagged 1 snapshot."),
+        _ => format!("Tagged {} snapshots.", count),
+    }
+}
+
+/// Formats a list of run numbers, for instance "runs 1, 3: passed".
+pub fn format_runs(runs: &[usize], verdict: &str) -> String {
+    let runs_str: Vec<String> = runs.iter().map(|run| run.to_string()).collect();
+    let plural = if runs.len() > 1 { "s" } else { "" };
+    format!("run{} {}: {}", plural, runs_str.join(", "), verdict)
+}
+
 /// Normalizes a string for use a file name.
 pub fn normalize_name(name: &str) -> String {
     name.trim().replace(' ', "_").replace('\t', "_")
@@ -50,3 +213,36 @@ pub fn get_random_name() -> String {
     random_name.extend(thread_rng().sample_iter(&Alphanumeric).take(30));
     random_name
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::cmd;
+
+    #[test]
+    fn test_group_runs() {
+        let run = |cmd: &str| cmd::execute(cmd, ".").ok().unwrap();
+        let mut snap = to_snapshot(String::from("x"), None, Vec::new(), String::from(""), run("echo a; echo 1 >&2"));
+
+        // An ignored stream may vary between runs
+        let results = vec![run("echo a; echo 2 >&2"), run("echo a; echo 3 >&2")];
+        assert!(repeat_status(&group_runs(&snap, results)) == SnapshotStatus::Failed);
+        snap.stderr_mode = StreamMode::Ignore;
+        let results = vec![run("echo a; echo 2 >&2"), run("echo a; echo 3 >&2")];
+        let outcomes = group_runs(&snap, results);
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].runs, vec![1, 2]);
+        assert!(repeat_status(&outcomes) == SnapshotStatus::Passed);
+
+        // Failing with different outputs is not flaky
+        let results = vec![run("echo b"), run("echo c; exit 1"), run("echo b")];
+        let outcomes = group_runs(&snap, results);
+        let runs: Vec<&[usize]> = outcomes.iter().map(|outcome| &outcome.runs[..]).collect();
+        assert_eq!(runs, vec![&[1, 3][..], &[2]]);
+        assert!(repeat_status(&outcomes) == SnapshotStatus::Failed);
+
+        // Flaky snapshots have mixed verdicts
+        let results = vec![run("echo a"), run("echo b")];
+        assert!(repeat_status(&group_runs(&snap, results)) == SnapshotStatus::Flaky);
+    }
+}
@@ -3,94 +3,229 @@ use std::fs::{remove_file, File};
 use std::io::{Read, Write};
 use std::path::Path;
 use std::process::Command;
-use regex::Regex;
 
-use crate::data::PARROT_PATH;
+use crate::data::{AnsiMode, ExpectedCode, Snapshot, StreamMode, Whitespace, PARROT_PATH};
 use crate::error::{wrap, Error};
 
 const FILE_NAME: &'static str = "PARROT_SNAPSHOT";
+/// Starts the line reporting why the description file was reopened.
+const ERROR_PREFIX: &str = "// Error: ";
 
 pub struct EditResult {
     pub name: Option<String>,
     pub description: Option<String>,
     pub tags: Vec<String>,
+    pub settings: Settings,
+}
+
+/// The snapshot settings, edited as `key: value` lines preceding the
+/// description.
+#[derive(Default, PartialEq)]
+pub struct Settings {
+    pub expected_code: ExpectedCode,
+    pub stdout_mode: StreamMode,
+    pub stderr_mode: StreamMode,
+    pub ansi: AnsiMode,
+    pub whitespace: Whitespace,
+}
+
+impl Settings {
+    /// Returns the settings of a snapshot.
+    pub fn of(snap: &Snapshot) -> Settings {
+        Settings {
+            expected_code: snap.expected_code.clone(),
+            stdout_mode: snap.stdout_mode.clone(),
+            stderr_mode: snap.stderr_mode.clone(),
+            ansi: snap.ansi,
+            whitespace: snap.whitespace,
+        }
+    }
+
+    /// Applies the settings to a snapshot.
+    pub fn apply(self, snap: &mut Snapshot) {
+        snap.expected_code = self.expected_code;
+        snap.stdout_mode = self.stdout_mode;
+        snap.stderr_mode = self.stderr_mode;
+        snap.ansi = self.ansi;
+        snap.whitespace = self.whitespace;
+    }
+
+    /// Builds settings from optional `(key, value)` pairs, such as command
+    /// line options.
+    pub fn from_options(options: &[(&str, &Option<String>)]) -> Result<Settings, Error> {
+        let mut settings = Settings::default();
+        settings.update(options)?;
+        Ok(settings)
+    }
+
+    /// Changes the settings given as optional `(key, value)` pairs, other
+    /// settings are kept.
+    pub fn update(&mut self, options: &[(&str, &Option<String>)]) -> Result<(), Error> {
+        for (key, value) in options {
+            if let Some(value) = value {
+                self.set(key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the value of a setting.
+    /// Returns false if the key is not a setting.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, Error> {
+        match key {
+            "code" => self.expected_code = value.parse()?,
+            "stdout" => self.stdout_mode = value.parse()?,
+            "stderr" => self.stderr_mode = value.parse()?,
+            "ansi" => self.ansi = value.parse()?,
+            "whitespace" => self.whitespace = value.parse()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Parses a `key: value` setting line.
+    /// Returns false if the line is not a setting.
+    fn parse_line(&mut self, line: &str) -> Result<bool, Error> {
+        match line.find(':') {
+            Some(idx) => self.set(line[..idx].trim(), &line[idx + 1..]),
+            None => Ok(false),
+        }
+    }
 }
 
 /// Opens an empty description in the user's favorite editor.
-pub fn open_empty<P: AsRef<Path>>(path: P, cmd: &str) -> Result<EditResult, Error> {
-    open(path, "", "", cmd)
+pub fn open_empty<P: AsRef<Path>>(path: P, cmd: &str, settings: &Settings) -> Result<EditResult, Error> {
+    open(path, "", "", &[], settings, cmd)
 }
 
 /// Opens the snapshot's description file in the user's favorite editor.
-pub fn open_snap<P: AsRef<Path>>(path: P, name: &str, description: &str, cmd: &str) -> Result<EditResult, Error> {
-    open(path, name, description, cmd)
+pub fn open_snap<P: AsRef<Path>>(path: P, snap: &Snapshot) -> Result<EditResult, Error> {
+    let description = match snap.description.as_ref() {
+        Some(desc) => desc,
+        None => "",
+    };
+    open(path, &snap.name, description, &snap.tags, &Settings::of(snap), &snap.cmd)
 }
 
 /// Opens a new description file in the user's favorite editor.
-fn open<P: AsRef<Path>>(path: P, name: &str, description: &str, cmd: &str) -> Result<EditResult, Error> {
+fn open<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    description: &str,
+    tags: &[String],
+    settings: &Settings,
+    cmd: &str,
+) -> Result<EditResult, Error> {
+    let tags: Vec<String> = tags.iter().map(|tag| format!("#{}", tag)).collect();
     let editor = var("EDITOR").expect("No 'EDITOR' environment variable.");
     let mut file_path = path.as_ref().to_owned();
     file_path.push(PARROT_PATH);
     file_path.push(FILE_NAME);
-    let mut file = wrap(
-        File::create(&file_path),
-        "Could not create description file, try using `parrot init` first.",
-    )?;
-    wrap(
-        write!(
-            file,
-            "{}\n\
-             {}\n\n\
-
-             // The first line will be used as snapshot name, the following as description.\n\
-             // If the first line is blank, a random name will be used.\n\
-             // Hastag in the description (#example) will serve as tag for the snapshot.\n\
-             // Characters after '//' are ignored.\n\
-             //\n\
-             // Test command: {}",
-            name, description, cmd
-        ),
-        "Could not write description file",
-    )?;
-
-    let status = wrap(
-        Command::new(editor).arg(&file_path).status(),
-        "An error occured with the text editor",
-    )?;
-    if !status.success() {
-        return Error::from_str("Aborting");
-    }
+    let mut content = format!(
+        "{}\n\
+         tags: {}\n\
+         code: {}\n\
+         stdout: {}\n\
+         stderr: {}\n\
+         ansi: {}\n\
+         whitespace: {}\n\n\
+         {}\n\n\
+
+         // The first line will be used as snapshot name, the lines following the first blank line as description.\n\
+         // The 'code:' line sets the expected exit code: exact, any, nonzero, a set (0,2) or a range (1-3).\n\
+         // The 'stdout:' and 'stderr:' lines set how outputs are compared: exact, ignore, lines (in any order),\n\
+         // contains <text>, regex <pattern> or json [ignore=<path>,...] [tolerance=<number>].\n\
+         // The 'ansi:' line sets how escape sequences are compared: keep, strip or semantic (same styles).\n\
+         // The 'whitespace:' line sets which whitespace differences are ignored: exact, all or a list of\n\
+         // trailing (spaces), eol (\\r\\n line endings), blank (lines) and collapse (runs of spaces).\n\
+         // If the first line is blank, a random name will be used.\n\
+         // The 'tags:' line sets the snapshot tags, separated by spaces (#example #slow).\n\
+         // Characters after '//' are ignored.\n\
+         //\n\
+         // Test command: {}",
+        name, tags.join(" "), settings.expected_code, settings.stdout_mode, settings.stderr_mode, settings.ansi, settings.whitespace, description, cmd
+    );
+
+    loop {
+        let mut file = wrap(
+            File::create(&file_path),
+            "Could not create description file, try using `parrot init` first.",
+        )?;
+        wrap(file.write_all(content.as_bytes()), "Could not write description file")?;
 
-    let mut content = String::new();
-    wrap(
+        let status = wrap(
+            Command::new(&editor).arg(&file_path).status(),
+            "An error occured with the text editor",
+        )?;
+        if !status.success() {
+            let _ = remove_file(&file_path);
+            return Error::from_str("Aborting");
+        }
+
+        let mut edited = String::new();
         wrap(
-            File::open(&file_path),
-            "Could not open description file after editing",
-        )?
-        .read_to_string(&mut content),
-        "Could not read the description file",
-    )?;
-    let _ = remove_file(&file_path);
-
-    Ok(parse_file(content))
+            wrap(
+                File::open(&file_path),
+                "Could not open description file after editing",
+            )?
+            .read_to_string(&mut edited),
+            "Could not read the description file",
+        )?;
+
+        match parse_file(edited.clone()) {
+            Ok(result) => {
+                let _ = remove_file(&file_path);
+                return Ok(result);
+            }
+            // Reopen the file with the error, the edit is given up only if
+            // the error is left unchanged.
+            Err(err) if edited != content => {
+                content = with_error(&edited, &err.message);
+            }
+            Err(err) => {
+                let _ = remove_file(&file_path);
+                return Err(err);
+            }
+        }
+    }
 }
 
-/// Parse the content of the description file and return both title, description
-/// and tags.
-fn parse_file(content: String) -> EditResult {
+/// Parse the content of the description file and return both title, description,
+/// tags and settings.
+fn parse_file(content: String) -> Result<EditResult, Error> {
     let lines = content.split('\n');
     let mut name = String::from("");
     let mut description = String::from("");
     let mut tags = Vec::new();
+    let mut settings = Settings::default();
     let mut is_title = true;
-    for line in lines {
-        let (line, has_comment)  = strip_comment(line);
+    let mut is_setting = true;
+    for raw_line in lines {
+        let (line, has_comment) = strip_comment(raw_line);
         if is_title {
             name.push_str(line.trim());
             is_title = false;
             continue;
         }
-       
+
+        // Tags and settings come before the first blank line, so that the
+        // description may start like a setting. Settings are parsed before
+        // stripping comments as patterns may contain '//'.
+        if is_setting {
+            if raw_line.trim().is_empty() {
+                is_setting = false;
+                continue;
+            }
+            if let Some(value) = line.trim_start().strip_prefix("tags:") {
+                tags = parse_tags(value)?;
+                continue;
+            }
+            if settings.parse_line(raw_line)? || (line.trim().is_empty() && has_comment) {
+                continue;
+            }
+            is_setting = false;
+        }
+
         if line.len() > 0 || !has_comment {
             description.push_str(line);
             description.push_str("\n");
@@ -100,11 +235,6 @@ fn parse_file(content: String) -> EditResult {
     // Remove leadin/trainling whitespaces
     description = description.trim().to_owned();
 
-    let re = Regex::new(r"#[a-zA-Z0-9_-]+").unwrap();
-    for tag in re.captures_iter(&description) {
-        tags.push(tag[0][1..].to_owned());
-    }
-    
     let name = if name.len() > 0 {
         Some(name)
     } else {
@@ -115,11 +245,35 @@ fn parse_file(content: String) -> EditResult {
     } else {
         None
     };
-    EditResult {
+    Ok(EditResult {
         name,
         description,
-        tags
+        tags,
+        settings,
+    })
+}
+
+/// Parses the tags of a `tags:` line, separated by spaces and optionally
+/// starting with '#'.
+fn parse_tags(line: &str) -> Result<Vec<String>, Error> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in line.split_whitespace() {
+        let tag = tag.strip_prefix('#').unwrap_or(tag);
+        if tag.is_empty() || !tag.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Error::from_str(&format!("Invalid tag '{}'.", tag));
+        }
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_owned());
+        }
     }
+    Ok(tags)
+}
+
+/// Appends an error to the content of the description file, in place of the
+/// error of the previous attempt.
+fn with_error(content: &str, message: &str) -> String {
+    let lines: Vec<&str> = content.lines().filter(|line| !line.starts_with(ERROR_PREFIX)).collect();
+    format!("{}\n{}{}", lines.join("\n").trim_end(), ERROR_PREFIX, message.replace('\n', " "))
 }
 
 /// Return a string slice stripped form the eventual comment.
@@ -130,3 +284,37 @@ fn strip_comment(line: &str) -> (&str, bool) {
     let has_comment = iterator.next().is_some();
     (line, has_comment)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_file() {
+        let content = "name\ntags: #net slow\ncode: 1-3\nstdout: regex a//b\n\nstderr: is the description\ncode: too\n// comment";
+        let result = parse_file(String::from(content)).ok().unwrap();
+        assert_eq!(result.name, Some(String::from("name")));
+        assert_eq!(result.tags, vec![String::from("net"), String::from("slow")]);
+        assert!(result.settings.expected_code == ExpectedCode::Range(1, 3));
+        assert!(result.settings.stdout_mode == "regex a//b".parse().ok().unwrap());
+        assert_eq!(result.description, Some(String::from("stderr: is the description\ncode: too")));
+
+        // Descriptions may directly follow the settings
+        let result = parse_file(String::from("name\ncode: any\ndescription")).ok().unwrap();
+        assert!(result.settings.expected_code == ExpectedCode::Ignore);
+        assert_eq!(result.description, Some(String::from("description")));
+
+        // Should return an error
+        assert!(parse_file(String::from("name\ncode: wrong")).is_err());
+        assert!(parse_file(String::from("name\nstdout: regex (")).is_err());
+        assert!(parse_file(String::from("name\ntags: #a!")).is_err());
+    }
+
+    #[test]
+    fn test_with_error() {
+        let content = with_error("name\ncode: wrong\n\n// comment\n", "Invalid code.");
+        assert_eq!(content, "name\ncode: wrong\n\n// comment\n// Error: Invalid code.");
+        let content = with_error(&content.replace("wrong", "1"), "Invalid\ntag.");
+        assert_eq!(content, "name\ncode: 1\n\n// comment\n// Error: Invalid tag.");
+    }
+}
@@ -0,0 +1,263 @@
+use serde_json::Value;
+
+/// A segment of a JSON path, such as `.name` or `[3]`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+    /// Matches any key or index, only valid in patterns.
+    Wildcard,
+}
+
+/// A structural difference between two JSON values.
+#[derive(Debug, PartialEq)]
+pub enum Difference<'a> {
+    Changed(String, &'a Value, &'a Value),
+    Removed(String, &'a Value),
+    Added(String, &'a Value),
+}
+
+/// Returns the structural differences between two JSON values: key order is
+/// not significant, values at a path matching one of the `ignore` patterns are
+/// skipped and numbers are equal if they differ by at most `tolerance`.
+pub fn get_json_diff<'a>(
+    old: &'a Value,
+    new: &'a Value,
+    ignore: &[Vec<Segment>],
+    tolerance: f64,
+) -> Vec<Difference<'a>> {
+    let mut diff = Vec::new();
+    let mut path = Vec::new();
+    compare(old, new, &mut path, ignore, tolerance, &mut diff);
+    diff
+}
+
+/// Recursively compares two values, `path` is the location of both values.
+fn compare<'a>(
+    old: &'a Value,
+    new: &'a Value,
+    path: &mut Vec<Segment>,
+    ignore: &[Vec<Segment>],
+    tolerance: f64,
+    diff: &mut Vec<Difference<'a>>,
+) {
+    if is_ignored(path, ignore) {
+        return;
+    }
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                path.push(Segment::Key(key.clone()));
+                match new_map.get(key) {
+                    Some(new_value) => compare(old_value, new_value, path, ignore, tolerance, diff),
+                    None if !is_ignored(path, ignore) => diff.push(Difference::Removed(format_path(path), old_value)),
+                    None => (),
+                }
+                path.pop();
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    path.push(Segment::Key(key.clone()));
+                    if !is_ignored(path, ignore) {
+                        diff.push(Difference::Added(format_path(path), new_value));
+                    }
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            let n = std::cmp::max(old_items.len(), new_items.len());
+            for idx in 0..n {
+                path.push(Segment::Index(idx));
+                match (old_items.get(idx), new_items.get(idx)) {
+                    (Some(old_item), Some(new_item)) => compare(old_item, new_item, path, ignore, tolerance, diff),
+                    (Some(old_item), None) if !is_ignored(path, ignore) => {
+                        diff.push(Difference::Removed(format_path(path), old_item))
+                    }
+                    (None, Some(new_item)) if !is_ignored(path, ignore) => {
+                        diff.push(Difference::Added(format_path(path), new_item))
+                    }
+                    _ => (),
+                }
+                path.pop();
+            }
+        }
+        (Value::Number(old_number), Value::Number(new_number)) => {
+            let within_tolerance = match (old_number.as_f64(), new_number.as_f64()) {
+                (Some(a), Some(b)) => (a - b).abs() <= tolerance,
+                _ => false,
+            };
+            if old_number != new_number && !within_tolerance {
+                diff.push(Difference::Changed(format_path(path), old, new));
+            }
+        }
+        _ => {
+            if old != new {
+                diff.push(Difference::Changed(format_path(path), old, new));
+            }
+        }
+    }
+}
+
+/// Returns true if the path matches one of the ignored patterns.
+fn is_ignored(path: &[Segment], ignore: &[Vec<Segment>]) -> bool {
+    ignore.iter().any(|pattern| {
+        pattern.len() == path.len()
+            && pattern
+                .iter()
+                .zip(path)
+                .all(|(p, s)| *p == Segment::Wildcard || p == s)
+    })
+}
+
+/// Formats a path, for instance `$.items[3].name`.
+pub fn format_path(path: &[Segment]) -> String {
+    let mut formatted = String::from("$");
+    for segment in path {
+        match segment {
+            Segment::Key(key) if is_identifier(key) => {
+                formatted.push('.');
+                formatted.push_str(key);
+            }
+            Segment::Key(key) => formatted.push_str(&format!("[{}]", Value::String(key.clone()))),
+            Segment::Index(idx) => formatted.push_str(&format!("[{}]", idx)),
+            Segment::Wildcard => formatted.push_str("[*]"),
+        }
+    }
+    formatted
+}
+
+/// Parses a path pattern, such as `$.items[*].id` or `$["a key"]`.
+pub fn parse_path(pattern: &str) -> Result<Vec<Segment>, String> {
+    let error = || format!("Invalid JSON path '{}', expected a path such as $.items[*].id", pattern);
+    let mut chars = pattern.trim().chars().peekable();
+    if chars.next() != Some('$') {
+        return Err(error());
+    }
+    let mut path = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                let mut key = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    key.push(c);
+                    chars.next();
+                }
+                match key.as_str() {
+                    "" => return Err(error()),
+                    "*" => path.push(Segment::Wildcard),
+                    _ => path.push(Segment::Key(key)),
+                }
+            }
+            '[' => {
+                let mut inner = String::new();
+                let mut in_string = false;
+                loop {
+                    match chars.next() {
+                        None => return Err(error()),
+                        Some(']') if !in_string => break,
+                        Some('\\') if in_string => {
+                            inner.push('\\');
+                            inner.extend(chars.next());
+                        }
+                        Some(c) => {
+                            if c == '"' {
+                                in_string = !in_string;
+                            }
+                            inner.push(c);
+                        }
+                    }
+                }
+                let inner = inner.trim();
+                if inner == "*" {
+                    path.push(Segment::Wildcard);
+                } else if let Ok(idx) = inner.parse::<usize>() {
+                    path.push(Segment::Index(idx));
+                } else if let Ok(Value::String(key)) = serde_json::from_str(inner) {
+                    path.push(Segment::Key(key));
+                } else {
+                    return Err(error());
+                }
+            }
+            _ => return Err(error()),
+        }
+    }
+    Ok(path)
+}
+
+/// Parses a list of path patterns, invalid patterns are skipped.
+pub fn parse_paths(patterns: &[String]) -> Vec<Vec<Segment>> {
+    patterns.iter().filter_map(|pattern| parse_path(pattern).ok()).collect()
+}
+
+/// Returns true if a key can be written in dot notation.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_path() {
+        let items = Segment::Key(String::from("items"));
+
+        // Should succeed
+        assert_eq!(parse_path("$"), Ok(vec![]));
+        assert_eq!(
+            parse_path("$.items[3].name"),
+            Ok(vec![items.clone(), Segment::Index(3), Segment::Key(String::from("name"))])
+        );
+        assert_eq!(parse_path("$.items[*]"), Ok(vec![items.clone(), Segment::Wildcard]));
+        assert_eq!(parse_path("$.*"), Ok(vec![Segment::Wildcard]));
+        assert_eq!(parse_path("$[\"a.b]\"]"), Ok(vec![Segment::Key(String::from("a.b]"))]));
+
+        // Should return an error
+        assert!(parse_path("items").is_err());
+        assert!(parse_path("$.").is_err());
+        assert!(parse_path("$[3").is_err());
+        assert!(parse_path("$[a]").is_err());
+    }
+
+    #[test]
+    fn test_format_path() {
+        let path = vec![
+            Segment::Key(String::from("items")),
+            Segment::Index(3),
+            Segment::Key(String::from("a key")),
+        ];
+        assert_eq!(format_path(&path), "$.items[3][\"a key\"]");
+        assert_eq!(parse_path(&format_path(&path)), Ok(path));
+    }
+
+    #[test]
+    fn test_json_diff() {
+        let old = json!({"id": 1, "items": [{"name": "a"}, {"name": "b"}], "time": 1.0, "removed": true});
+        let new = json!({"time": 1.05, "items": [{"name": "a"}, {"name": "c"}, {"name": "d"}], "id": 2});
+        let id = parse_path("$.id").unwrap();
+
+        // Key order is not significant
+        let reordered = json!({"removed": true, "time": 1.0, "items": [{"name": "a"}, {"name": "b"}], "id": 1});
+        assert_eq!(get_json_diff(&old, &reordered, &[], 0.0), vec![]);
+
+        let diff = get_json_diff(&old, &new, &[id], 0.1);
+        let expected_diff = vec![
+            Difference::Changed(String::from("$.items[1].name"), &old["items"][1]["name"], &new["items"][1]["name"]),
+            Difference::Added(String::from("$.items[2]"), &new["items"][2]),
+            Difference::Removed(String::from("$.removed"), &old["removed"]),
+        ];
+        assert_eq!(diff, expected_diff);
+
+        let diff = get_json_diff(&old["time"], &new["time"], &[], 0.0);
+        assert_eq!(diff, vec![Difference::Changed(String::from("$"), &old["time"], &new["time"])]);
+    }
+}
@@ -2,18 +2,20 @@ use cli::Command;
 use std::process::exit;
 use error::Log;
 
+mod ansi;
 mod cli;
 mod data;
 mod diff;
 mod driver;
 mod editor;
 mod error;
+mod json;
 mod term;
 mod parser;
 
 fn main() {
     let config = cli::parse();
-    let mut context = driver::Context::new(config.path).unwrap_log();
+    let mut context = driver::Context::new(config.path, config.context, config.diff, config.layout).unwrap_log();
     match config.cmd {
         Some(Command::Init {}) => {
             context.init();
@@ -21,16 +23,57 @@ fn main() {
         Some(Command::Add {
             ref cmd,
             ref name,
+            ref code,
+            ref stdout,
+            ref stderr,
+            ref ansi,
+            ref whitespace,
             yes,
-        }) => context.add(cmd, name, yes),
-        Some(Command::Run {}) => {
-            if context.run() {
+        }) => {
+            let options = [
+                ("code", code),
+                ("stdout", stdout),
+                ("stderr", stderr),
+                ("ansi", ansi),
+                ("whitespace", whitespace),
+            ];
+            let settings = editor::Settings::from_options(&options).unwrap_log();
+            context.add(cmd, name, settings, yes)
+        }
+        Some(Command::Run { repeat, tag_flaky }) => {
+            if context.run(repeat, tag_flaky) {
                 exit(0);
             } else {
                 exit(1);
             }
         },
+        Some(Command::Diff { ref name, ref output }) => {
+            if context.diff(name, output) {
+                exit(0);
+            } else {
+                exit(1);
+            }
+        }
         Some(Command::Exec { ref cmd }) => context.exec(cmd),
+        Some(Command::Set {
+            ref name,
+            ref code,
+            ref stdout,
+            ref stderr,
+            ref ansi,
+            ref whitespace,
+        }) => {
+            let options = [
+                ("code", code),
+                ("stdout", stdout),
+                ("stderr", stderr),
+                ("ansi", ansi),
+                ("whitespace", whitespace),
+            ];
+            context.set(name, &options)
+        }
+        Some(Command::Tag { ref name, ref changes }) => context.tag(name, changes),
+        Some(Command::Tui {}) => context.tui(),
         None => {
             context.repl();
         }
@@ -1,27 +1,34 @@
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_while, take_while1};
-use nom::character::complete::one_of;
-use nom::combinator::{map, peek, value};
+use nom::character::complete::{digit1, one_of};
+use nom::combinator::{cut, map, map_opt, map_res, peek, value, verify};
 use nom::sequence::{preceded, terminated};
 use nom::IResult;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
-enum CommandKeyword {
+pub enum CommandKeyword {
     Quit,
     Clear,
     Help,
     Edit,
     Run,
+    Repeat,
     Show,
     Update,
     Delete,
     Filter,
+    Mark,
+    Unmark,
+    Pop,
+    Tag,
+    Tags,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Target {
     Selected,
     All,
+    Marked,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -31,7 +38,88 @@ pub enum Filter {
     Passed,
     Failed,
     Waiting,
-    Deleted,
+    Flaky,
+    /// A snapshot field matching a pattern.
+    Field(Field, Pattern),
+    /// The recorded exit code compared to a value.
+    Code(Comparison, i32),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Field {
+    Name,
+    Cmd,
+    Description,
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Pattern {
+    Contains(String),
+    Regex(Matcher),
+    /// A shell pattern matching the whole field, with `*`, `?` and `[...]`.
+    Glob(Matcher),
+}
+
+/// A regular expression compiled once when the filter is parsed, and compared
+/// by its source.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    source: String,
+    regex: regex::bytes::Regex,
+}
+
+impl Matcher {
+    /// Compiles a regular expression, returns None if it is invalid.
+    pub fn regex(pattern: &str) -> Option<Matcher> {
+        let regex = regex::bytes::Regex::new(pattern).ok()?;
+        Some(Matcher {
+            source: pattern.to_owned(),
+            regex,
+        })
+    }
+
+    /// Compiles a glob, returns None if it is invalid.
+    pub fn glob(glob: &str) -> Option<Matcher> {
+        let regex = regex::bytes::Regex::new(&glob_to_regex(glob)?).ok()?;
+        Some(Matcher {
+            source: glob.to_owned(),
+            regex,
+        })
+    }
+
+    /// Returns true if the text matches.
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Matcher) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Matcher {}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TagChange {
+    Add(String),
+    Remove(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -41,16 +129,25 @@ pub enum Command {
     Help,
     Edit,
     Run(Target),
+    Repeat(usize, Target),
     Show(Target),
     Update(Target),
     Delete(Target),
     Filter(Filter),
+    Mark,
+    Unmark,
+    /// Removes a filter from the stack, the latest one if no index is given.
+    Pop(Option<usize>),
+    Tag(Vec<TagChange>, Target),
+    /// Lists the tags along with their statistics.
+    Tags,
 }
 
 #[derive(Debug)]
 struct Error<I> {
     pub kind: ErrorKind<I>,
-    backtrace: Vec<Error<I>>,
+    /// The remaining input where the error occurred.
+    input: I,
 }
 
 #[derive(Debug, PartialEq)]
@@ -73,7 +170,7 @@ fn whitespaces(i: &str) -> CResult<&str, &str> {
 /// Looks for a separator, does not consume it.
 /// EOF counts as a separator.
 fn peek_separator(i: &str) -> CResult<&str, ()> {
-    let chars = " \t\r\n#+-*~;";
+    let chars = " \t\r\n#+-*@~?;()/";
     if i.len() == 0 {
         Ok((i, ()))
     } else {
@@ -87,7 +184,7 @@ fn end_of_script(i: &str) -> CResult<&str, &str> {
     if i.len() == 0 {
         Ok((i, ""))
     } else {
-        Err(Error::recoverable(ErrorKind::Nom(i, nom::error::ErrorKind::NoneOf)))
+        Err(Error::recoverable(i, nom::error::ErrorKind::NoneOf))
     }
 }
 
@@ -104,6 +201,11 @@ fn name(i: &str) -> CResult<&str, &str> {
     take_while1(is_name)(i)
 }
 
+/// Returns true if the text is parsed back as the same name.
+fn is_name(text: &str) -> bool {
+    name(text) == Ok(("", text)) && !["and", "or", "not"].contains(&text)
+}
+
 /// Parses a hashtag.
 fn hashtag(i: &str) -> CResult<&str, &str> {
     preceded(tag("#"), name)(i)
@@ -114,36 +216,336 @@ fn hashtag(i: &str) -> CResult<&str, &str> {
 fn no_args_left(i: &str, cmd: Command) -> CResult<&str, Command> {
     match end_of_command(i) {
         Ok((i, _)) => Ok((i, cmd)),
-        Err(_) => Err(Error::custom(ErrorKind::TooManyArguments(cmd))),
+        Err(_) => Err(Error::custom(i, ErrorKind::TooManyArguments(cmd))),
     }
 }
 
-/// Parses a target, that is either no argument or '*'.
+/// Parses a target, that is either no argument, '*' or '@' for marked snapshots.
 /// If no argument is found, the target is assumed to be 'Selected'.
 fn target(i: &str, cmd: CommandKeyword) -> CResult<&str, Target> {
     let (i, _) = whitespaces(i)?;
-    let selected = value(Target::Selected, end_of_command);
+    // The end of the command is left to `no_args_left`
+    let selected = value(Target::Selected, peek(end_of_command));
     let all = value(Target::All, tag("*"));
-    let target = alt((all, selected));
+    let marked = value(Target::Marked, tag("@"));
+    let target = alt((all, marked, selected));
     let target = preceded(whitespaces, target);
     match target(i) {
         Ok(t) => Ok(t),
-        Err(err) => Err(Error::custom_with_backtrace(ErrorKind::UnexpectedArgument(cmd), err)),
+        Err(err) => Err(Error::custom_from(ErrorKind::UnexpectedArgument(cmd), err)),
     }
 }
 
-/// Parses a filter argument.
-fn filter_arg(i: &str) -> CResult<&str, Filter> {
+/// Parses a repeat count, that is a strictly positive integer.
+fn repeat_count(i: &str) -> CResult<&str, usize> {
+    let count = map_res(digit1, |n: &str| n.parse::<usize>());
+    let count = verify(count, |n: &usize| *n > 0);
+    let count = preceded(whitespaces, count);
+    match count(i) {
+        Ok(n) => Ok(n),
+        Err(err) => Err(Error::custom_from(
+            ErrorKind::UnexpectedArgument(CommandKeyword::Repeat),
+            err,
+        )),
+    }
+}
+
+/// Parses an optional filter index, that is a strictly positive integer.
+fn filter_index(i: &str) -> CResult<&str, Option<usize>> {
+    let index = map_res(digit1, |n: &str| n.parse::<usize>());
+    let index = verify(index, |n: &usize| *n > 0);
+    let index = preceded(whitespaces, index);
+    match end_of_command(i) {
+        Ok(_) => Ok((i, None)),
+        Err(_) => match index(i) {
+            Ok((i, n)) => Ok((i, Some(n))),
+            Err(err) => Err(Error::custom_from(
+                ErrorKind::UnexpectedArgument(CommandKeyword::Pop),
+                err,
+            )),
+        },
+    }
+}
+
+/// Parses a tag change, either `+tag` or `-tag`.
+fn tag_change(i: &str) -> CResult<&str, TagChange> {
+    let add = map(preceded(tag("+"), name), |t: &str| TagChange::Add(t.to_owned()));
+    let remove = map(preceded(tag("-"), name), |t: &str| TagChange::Remove(t.to_owned()));
+    preceded(whitespaces, alt((add, remove)))(i)
+}
+
+/// Parses at least one tag change.
+fn tag_changes(i: &str) -> CResult<&str, Vec<TagChange>> {
+    let (mut i, change) = match tag_change(i) {
+        Ok(change) => change,
+        Err(err) => {
+            return Err(Error::custom_from(
+                ErrorKind::UnexpectedArgument(CommandKeyword::Tag),
+                err,
+            ))
+        }
+    };
+    let mut changes = vec![change];
+    while let Ok((rest, change)) = tag_change(i) {
+        changes.push(change);
+        i = rest;
+    }
+    Ok((i, changes))
+}
+
+/// Returns a parser for a keyword of filter expressions, such as `and`.
+fn filter_keyword<'a>(keyword: &'a str) -> impl Fn(&'a str) -> CResult<&'a str, &'a str> {
+    move |i: &str| terminated(preceded(whitespaces, tag(keyword)), peek_separator)(i)
+}
+
+/// Parses a regular expression between slashes, such as `/^cli_/`. Slashes
+/// are escaped with a backslash.
+fn regex_literal(i: &str) -> CResult<&str, String> {
+    let (rest, _) = tag("/")(i)?;
+    let mut pattern = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '/' => return Ok((&rest[idx + 1..], pattern)),
+            '\\' => match chars.next() {
+                Some((_, '/')) => pattern.push('/'),
+                Some((_, c)) => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+                None => break,
+            },
+            c => pattern.push(c),
+        }
+    }
+    Err(Error::recoverable(i, nom::error::ErrorKind::Char))
+}
+
+/// Parses a string literal between double quotes. Double quotes and
+/// backslashes are escaped with a backslash, as well as newlines (`\n`) and
+/// tabs (`\t`), other escapes are kept as is for regular expressions.
+fn quoted(i: &str) -> CResult<&str, String> {
+    let (rest, _) = tag("\"")(i)?;
+    let mut text = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&rest[idx + 1..], text)),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => text.push('\n'),
+                Some((_, 't')) => text.push('\t'),
+                Some((_, c @ '"')) | Some((_, c @ '\\')) => text.push(c),
+                Some((_, c)) => {
+                    text.push('\\');
+                    text.push(c);
+                }
+                None => break,
+            },
+            c => text.push(c),
+        }
+    }
+    Err(Error::recoverable(i, nom::error::ErrorKind::Char))
+}
+
+/// Parses a word, that ends at a whitespace, a semicolon or a parenthesis.
+fn word(i: &str) -> CResult<&str, &str> {
+    take_while1(|c: char| !c.is_whitespace() && !";()".contains(c))(i)
+}
+
+/// Returns true if the text contains glob wildcards.
+fn is_glob(text: &str) -> bool {
+    text.contains(|c| "*?[".contains(c))
+}
+
+/// Translates a glob to a regular expression matching the whole text, newlines
+/// included. Returns None if a bracket is not closed.
+fn glob_to_regex(glob: &str) -> Option<String> {
+    let mut pattern = String::from("(?s)^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => {
+                            closed = true;
+                            break;
+                        }
+                        '[' | '\\' | '&' | '~' => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        c => class.push(c),
+                    }
+                }
+                if !closed {
+                    return None;
+                }
+                match class.strip_prefix('!') {
+                    Some(class) => pattern.push_str(&format!("[^{}]", class)),
+                    None => pattern.push_str(&format!("[{}]", class)),
+                }
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Some(pattern)
+}
+
+/// Writes a text as a string literal, see `quoted`.
+fn write_quoted(f: &mut std::fmt::Formatter, text: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Returns true if the text is parsed back as the same word pattern.
+fn is_plain_word(text: &str) -> bool {
+    !is_glob(text) && !text.starts_with(|c| "~/\"".contains(c)) && word(text) == Ok(("", text))
+}
+
+/// Parses a valid regular expression, either between slashes or after `~`.
+fn regex(i: &str) -> CResult<&str, Pattern> {
+    let tilde = preceded(tag("~"), alt((quoted, map(word, String::from))));
+    let parser = alt((regex_literal, tilde));
+    map(map_opt(parser, |pattern| Matcher::regex(&pattern)), Pattern::Regex)(i)
+}
+
+/// Parses the pattern of a field filter: a regular expression, a quoted
+/// text, a glob or a word.
+fn pattern(i: &str) -> CResult<&str, Pattern> {
+    let quoted = map(quoted, Pattern::Contains);
+    let word = verify(word, |word: &str| !word.starts_with('"'));
+    let word = map_opt(word, |word| match is_glob(word) {
+        true => Matcher::glob(word).map(Pattern::Glob),
+        false => Some(Pattern::Contains(word.to_owned())),
+    });
+    alt((regex, quoted, word))(i)
+}
+
+/// Parses a field filter, such as `cmd:cargo`.
+fn field_filter(i: &str) -> CResult<&str, Filter> {
+    let name = value(Field::Name, tag("name:"));
+    let cmd = value(Field::Cmd, tag("cmd:"));
+    let description = value(Field::Description, tag("desc:"));
+    let stdout = value(Field::Stdout, tag("stdout:"));
+    let stderr = value(Field::Stderr, tag("stderr:"));
+    let (i, field) = alt((name, cmd, description, stdout, stderr))(i)?;
+    let (i, pattern) = cut(pattern)(i)?;
+    Ok((i, Filter::Field(field, pattern)))
+}
+
+/// Parses an exit code filter, such as `code:!=0`.
+fn code_filter(i: &str) -> CResult<&str, Filter> {
+    let (i, _) = tag("code:")(i)?;
+    let comparison = alt((
+        value(Comparison::NotEqual, tag("!=")),
+        value(Comparison::LessOrEqual, tag("<=")),
+        value(Comparison::GreaterOrEqual, tag(">=")),
+        value(Comparison::Less, tag("<")),
+        value(Comparison::Greater, tag(">")),
+        value(Comparison::Equal, tag("=")),
+        value(Comparison::Equal, tag("")),
+    ));
+    let (i, comparison) = comparison(i)?;
+    let (i, code) = cut(map_res(digit1, |n: &str| n.parse::<i32>()))(i)?;
+    Ok((i, Filter::Code(comparison, code)))
+}
+
+/// Parses a name glob, such as `cli_*`.
+fn name_glob(i: &str) -> CResult<&str, Filter> {
+    let glob = take_while1(|c: char| c.is_alphanumeric() || "-_*?[]".contains(c));
+    let glob = verify(glob, |glob: &str| is_glob(glob) && glob.contains(char::is_alphanumeric));
+    map_opt(glob, |glob: &str| {
+        Matcher::glob(glob).map(|glob| Filter::Field(Field::Name, Pattern::Glob(glob)))
+    })(i)
+}
+
+/// Parses a single filter: on a field, a name, a tag or a status.
+fn filter_atom(i: &str) -> CResult<&str, Filter> {
+    let field = alt((field_filter, code_filter));
+    let name_regex = map_opt(regex_literal, |pattern| {
+        Matcher::regex(&pattern).map(|regex| Filter::Field(Field::Name, Pattern::Regex(regex)))
+    });
     let waiting = value(Filter::Waiting, tag("~"));
     let passed = value(Filter::Passed, tag("+"));
     let failed = value(Filter::Failed, tag("-"));
+    let flaky = value(Filter::Flaky, tag("?"));
     let hashtag = map(hashtag, move |t| Filter::Tag(t.to_owned()));
+    let name = verify(name, |n: &str| !["and", "or", "not"].contains(&n));
     let name = map(name, move |n| Filter::Name(n.to_owned()));
-    let parser = alt((waiting, passed, failed, hashtag, name));
-    let parser = preceded(whitespaces, parser);
-    match parser(i) {
+    let quoted_name = map(quoted, Filter::Name);
+    let parser = alt((
+        field,
+        name_regex,
+        name_glob,
+        waiting,
+        passed,
+        failed,
+        flaky,
+        hashtag,
+        name,
+        quoted_name,
+    ));
+    preceded(whitespaces, parser)(i)
+}
+
+/// Parses a negated filter, a parenthesized expression or a single filter.
+fn filter_unary(i: &str) -> CResult<&str, Filter> {
+    if let Ok((i, _)) = filter_keyword("not")(i) {
+        let (i, filter) = filter_unary(i)?;
+        return Ok((i, Filter::Not(Box::new(filter))));
+    }
+    let (i, _) = whitespaces(i)?;
+    if let Some(i) = i.strip_prefix('(') {
+        let (i, filter) = filter_expr(i)?;
+        let (i, _) = preceded(whitespaces, tag(")"))(i)?;
+        return Ok((i, filter));
+    }
+    filter_atom(i)
+}
+
+/// Parses filters joined by `and`.
+fn filter_and(i: &str) -> CResult<&str, Filter> {
+    let (mut i, mut filter) = filter_unary(i)?;
+    while let Ok((rest, _)) = filter_keyword("and")(i) {
+        let (rest, right) = filter_unary(rest)?;
+        filter = Filter::And(Box::new(filter), Box::new(right));
+        i = rest;
+    }
+    Ok((i, filter))
+}
+
+/// Parses a filter expression, `or` binds less tightly than `and`, which
+/// binds less tightly than `not`.
+fn filter_expr(i: &str) -> CResult<&str, Filter> {
+    let (mut i, mut filter) = filter_and(i)?;
+    while let Ok((rest, _)) = filter_keyword("or")(i) {
+        let (rest, right) = filter_and(rest)?;
+        filter = Filter::Or(Box::new(filter), Box::new(right));
+        i = rest;
+    }
+    Ok((i, filter))
+}
+
+/// Parses a filter argument.
+fn filter_arg(i: &str) -> CResult<&str, Filter> {
+    match filter_expr(i) {
         Ok(f) => Ok(f),
-        Err(err) => Err(Error::custom_with_backtrace(
+        Err(err) => Err(Error::custom_from(
             ErrorKind::UnexpectedArgument(CommandKeyword::Filter),
             err,
         )),
@@ -171,11 +573,19 @@ fn commands(i: &str) -> CResult<&str, Vec<Command>> {
     let help = command_keyword("help", "h", CommandKeyword::Help);
     let edit = command_keyword("edit", "e", CommandKeyword::Edit);
     let run = command_keyword("run", "r", CommandKeyword::Run);
+    let repeat = command_keyword("repeat", "rp", CommandKeyword::Repeat);
     let show = command_keyword("show", "s", CommandKeyword::Show);
     let update = command_keyword("update", "u", CommandKeyword::Update);
     let delete = command_keyword("delete", "d", CommandKeyword::Delete);
     let filter = command_keyword("filter", "f", CommandKeyword::Filter);
-    let keyword = alt((quit, clear, help, edit, run, show, update, delete, filter));
+    let mark = command_keyword("mark", "m", CommandKeyword::Mark);
+    let unmark = command_keyword("unmark", "um", CommandKeyword::Unmark);
+    let pop = command_keyword("pop", "p", CommandKeyword::Pop);
+    let tag = command_keyword("tag", "t", CommandKeyword::Tag);
+    let tags = command_keyword("tags", "ts", CommandKeyword::Tags);
+    let keyword = alt((
+        quit, clear, help, edit, run, repeat, show, update, delete, filter, mark, unmark, pop, tag, tags,
+    ));
     let mut commands = Vec::new();
     let mut i = i;
     loop {
@@ -186,10 +596,22 @@ fn commands(i: &str) -> CResult<&str, Vec<Command>> {
                 CommandKeyword::Clear => no_args_left(i, Command::Clear),
                 CommandKeyword::Help => no_args_left(i, Command::Help),
                 CommandKeyword::Edit => no_args_left(i, Command::Edit),
+                CommandKeyword::Mark => no_args_left(i, Command::Mark),
+                CommandKeyword::Unmark => no_args_left(i, Command::Unmark),
+                CommandKeyword::Tags => no_args_left(i, Command::Tags),
+                CommandKeyword::Pop => {
+                    let (i, n) = filter_index(i)?;
+                    no_args_left(i, Command::Pop(n))
+                }
                 CommandKeyword::Run => {
                     let (i, t) = target(i, CommandKeyword::Run)?;
                     no_args_left(i, Command::Run(t))
                 }
+                CommandKeyword::Repeat => {
+                    let (i, n) = repeat_count(i)?;
+                    let (i, t) = target(i, CommandKeyword::Repeat)?;
+                    no_args_left(i, Command::Repeat(n, t))
+                }
                 CommandKeyword::Show => {
                     let (i, t) = target(i, CommandKeyword::Show)?;
                     no_args_left(i, Command::Show(t))
@@ -206,8 +628,13 @@ fn commands(i: &str) -> CResult<&str, Vec<Command>> {
                     let (i, f) = filter_arg(i)?;
                     no_args_left(i, Command::Filter(f))
                 }
+                CommandKeyword::Tag => {
+                    let (i, changes) = tag_changes(i)?;
+                    let (i, t) = target(i, CommandKeyword::Tag)?;
+                    no_args_left(i, Command::Tag(changes, t))
+                }
             },
-            Err(err) => return Err(Error::custom_with_backtrace(ErrorKind::UnknownCommand, err)),
+            Err(err) => return Err(Error::custom_from(ErrorKind::UnknownCommand, err)),
         }?;
         i = input;
         commands.push(cmd);
@@ -219,6 +646,40 @@ fn commands(i: &str) -> CResult<&str, Vec<Command>> {
     }
 }
 
+/// Returns the completions of the word ending the input, along with the
+/// offset of that word. Command keywords are completed, as well as snapshot
+/// names and tags after `filter`, and tags after `tag`.
+pub fn get_completions(input: &str, names: &[String], tags: &[String]) -> (usize, Vec<String>) {
+    let command_start = input.rfind(';').map_or(0, |idx| idx + 1);
+    let command = &input[command_start..];
+    let word_start = command
+        .rfind(|c: char| c.is_whitespace() || c == '(')
+        .map_or(0, |idx| idx + 1);
+    let word = &command[word_start..];
+    let keyword = command.split_whitespace().next().unwrap_or("");
+    let candidates: Vec<String> = if command[..word_start].trim().is_empty() {
+        CommandKeyword::ALL.iter().map(|keyword| keyword.to_string()).collect()
+    } else if keyword == "f" || keyword == "filter" {
+        if word.starts_with('#') {
+            tags.iter().map(|tag| format!("#{}", tag)).collect()
+        } else {
+            names.to_vec()
+        }
+    } else if keyword == "t" || keyword == "tag" {
+        match word.chars().next() {
+            Some(sign @ '+') | Some(sign @ '-') => tags.iter().map(|tag| format!("{}{}", sign, tag)).collect(),
+            _ => Vec::new(),
+        }
+    } else {
+        Vec::new()
+    };
+    let completions = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .collect();
+    (command_start + word_start, completions)
+}
+
 pub fn parse(input: &str) -> Result<Vec<Command>, String> {
     match commands(input) {
         Ok((_, cmd)) => Ok(cmd),
@@ -228,16 +689,33 @@ pub fn parse(input: &str) -> Result<Vec<Command>, String> {
                 nom::Err::Error(err) => err,
                 nom::Err::Failure(err) => err,
             };
-            match err.kind {
-                ErrorKind::Nom(_, _) => Err(String::from("Failed to parse command")),
-                ErrorKind::UnknownCommand => Err(String::from("Unknown command")),
-                ErrorKind::UnexpectedArgument(cmd) => Err(format!("Unexpected argument in {}", cmd)),
-                ErrorKind::TooManyArguments(cmd) => Err(format!("Too many arguments in {}", cmd)),
-            }
+            let message = match err.kind {
+                ErrorKind::Nom(_, _) => String::from("Failed to parse command"),
+                ErrorKind::UnknownCommand => String::from("Unknown command"),
+                ErrorKind::UnexpectedArgument(cmd) => format!("Unexpected argument in {}", cmd),
+                ErrorKind::TooManyArguments(cmd) => format!("Too many arguments in {}", cmd),
+            };
+            Err(locate_error(input, err.input, &message))
         }
     }
 }
 
+/// Formats an error message with the column of the error, followed by the
+/// offending line and a caret pointing at the error.
+fn locate_error(input: &str, rest: &str, message: &str) -> String {
+    let offset = input.len() - rest.trim_start().len();
+    let line_start = input[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = input[offset..].find('\n').map_or(input.len(), |idx| offset + idx);
+    let column = input[line_start..offset].chars().count();
+    format!(
+        "{} at column {}\n  {}\n  {}^",
+        message,
+        column + 1,
+        &input[line_start..line_end],
+        " ".repeat(column)
+    )
+}
+
 impl std::fmt::Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -246,14 +724,128 @@ impl std::fmt::Display for Command {
             Command::Help => write!(f, "help"),
             Command::Edit => write!(f, "edit"),
             Command::Run(_) => write!(f, "run"),
+            Command::Repeat(_, _) => write!(f, "repeat"),
             Command::Show(_) => write!(f, "show"),
             Command::Update(_) => write!(f, "update"),
             Command::Delete(_) => write!(f, "delete"),
             Command::Filter(_) => write!(f, "filter"),
+            Command::Mark => write!(f, "mark"),
+            Command::Unmark => write!(f, "unmark"),
+            Command::Pop(_) => write!(f, "pop"),
+            Command::Tag(_, _) => write!(f, "tag"),
+            Command::Tags => write!(f, "tags"),
+        }
+    }
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Filter::Name(name) if is_name(name) => write!(f, "{}", name),
+            Filter::Name(name) => write_quoted(f, name),
+            Filter::Tag(tag) => write!(f, "#{}", tag),
+            Filter::Passed => write!(f, "+"),
+            Filter::Failed => write!(f, "-"),
+            Filter::Waiting => write!(f, "~"),
+            Filter::Flaky => write!(f, "?"),
+            // Name regexes can be written without the field
+            Filter::Field(Field::Name, pattern @ Pattern::Regex(_)) => write!(f, "{}", pattern),
+            Filter::Field(field, pattern) => write!(f, "{}:{}", field, pattern),
+            Filter::Code(comparison, code) => write!(f, "code:{}{}", comparison, code),
+            Filter::And(left, right) => {
+                // `or` binds less tightly, it must be grouped
+                let group = |filter: &Filter| match filter {
+                    Filter::Or(_, _) => format!("({})", filter),
+                    _ => filter.to_string(),
+                };
+                write!(f, "{} and {}", group(left), group(right))
+            }
+            Filter::Or(left, right) => write!(f, "{} or {}", left, right),
+            Filter::Not(filter) => match **filter {
+                Filter::And(_, _) | Filter::Or(_, _) => write!(f, "not ({})", filter),
+                _ => write!(f, "not {}", filter),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for TagChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TagChange::Add(tag) => write!(f, "+{}", tag),
+            TagChange::Remove(tag) => write!(f, "-{}", tag),
+        }
+    }
+}
+
+impl std::str::FromStr for TagChange {
+    type Err = String;
+
+    /// Parses a single tag change, such as a command line argument.
+    fn from_str(s: &str) -> Result<TagChange, String> {
+        match tag_change(s) {
+            Ok(("", change)) => Ok(change),
+            _ => Err(format!("Invalid tag change '{}', expected +<tag> or -<tag>", s)),
         }
     }
 }
 
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Field::Name => write!(f, "name"),
+            Field::Cmd => write!(f, "cmd"),
+            Field::Description => write!(f, "desc"),
+            Field::Stdout => write!(f, "stdout"),
+            Field::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pattern::Contains(text) if is_plain_word(text) => write!(f, "{}", text),
+            Pattern::Contains(text) => write_quoted(f, text),
+            Pattern::Regex(regex) => write!(f, "/{}/", regex.source.replace('/', "\\/")),
+            Pattern::Glob(glob) => write!(f, "{}", glob.source),
+        }
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Comparison::Equal => write!(f, ""),
+            Comparison::NotEqual => write!(f, "!="),
+            Comparison::Less => write!(f, "<"),
+            Comparison::LessOrEqual => write!(f, "<="),
+            Comparison::Greater => write!(f, ">"),
+            Comparison::GreaterOrEqual => write!(f, ">="),
+        }
+    }
+}
+
+impl CommandKeyword {
+    pub const ALL: [CommandKeyword; 15] = [
+        CommandKeyword::Clear,
+        CommandKeyword::Delete,
+        CommandKeyword::Edit,
+        CommandKeyword::Filter,
+        CommandKeyword::Help,
+        CommandKeyword::Mark,
+        CommandKeyword::Pop,
+        CommandKeyword::Quit,
+        CommandKeyword::Repeat,
+        CommandKeyword::Run,
+        CommandKeyword::Show,
+        CommandKeyword::Tag,
+        CommandKeyword::Tags,
+        CommandKeyword::Unmark,
+        CommandKeyword::Update,
+    ];
+}
+
 impl std::fmt::Display for CommandKeyword {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -262,33 +854,34 @@ impl std::fmt::Display for CommandKeyword {
             CommandKeyword::Help => write!(f, "help"),
             CommandKeyword::Edit => write!(f, "edit"),
             CommandKeyword::Run => write!(f, "run"),
+            CommandKeyword::Repeat => write!(f, "repeat"),
             CommandKeyword::Show => write!(f, "show"),
             CommandKeyword::Update => write!(f, "update"),
             CommandKeyword::Delete => write!(f, "delete"),
             CommandKeyword::Filter => write!(f, "filter"),
+            CommandKeyword::Mark => write!(f, "mark"),
+            CommandKeyword::Unmark => write!(f, "unmark"),
+            CommandKeyword::Pop => write!(f, "pop"),
+            CommandKeyword::Tag => write!(f, "tag"),
+            CommandKeyword::Tags => write!(f, "tags"),
         }
     }
 }
 
-impl<I> Error<I> {
+impl<I: Clone> Error<I> {
     /// Builds a custom (failure) error.
-    fn custom(kind: ErrorKind<I>) -> nom::Err<Self> {
-        nom::Err::Failure(Self {
-            kind,
-            backtrace: Vec::new(),
-        })
+    fn custom(input: I, kind: ErrorKind<I>) -> nom::Err<Self> {
+        nom::Err::Failure(Self { kind, input })
     }
 
-    /// Build a custom (recoverable) error.
-    fn recoverable(kind: ErrorKind<I>) -> nom::Err<Self> {
-        nom::Err::Error(Self {
-            kind,
-            backtrace: Vec::new(),
-        })
+    /// Build a (recoverable) nom error.
+    fn recoverable(input: I, kind: nom::error::ErrorKind) -> nom::Err<Self> {
+        nom::Err::Error(nom::error::ParseError::from_error_kind(input, kind))
     }
 
-    /// Builds a custom (failure) error, keeps the backtrace of a previous error.
-    fn custom_with_backtrace(kind: ErrorKind<I>, err: nom::Err<Self>) -> nom::Err<Self> {
+    /// Builds a custom (failure) error, located where a previous error
+    /// occurred, as it is closer to the offending input.
+    fn custom_from(kind: ErrorKind<I>, err: nom::Err<Self>) -> nom::Err<Self> {
         let err = match err {
             nom::Err::Incomplete(_) => {
                 panic!("Internal error: parser must use the 'complete' version of nom's combinators.")
@@ -296,29 +889,19 @@ impl<I> Error<I> {
             nom::Err::Error(err) => err,
             nom::Err::Failure(err) => err,
         };
-        let mut backtrace = Vec::with_capacity(err.backtrace.len() + 1);
-        backtrace.push(Self {
-            kind: err.kind,
-            backtrace: Vec::new(),
-        });
-        backtrace.extend(err.backtrace);
-        nom::Err::Failure(Self {
-            kind,
-            backtrace: Vec::new(),
-        })
+        Self::custom(err.input, kind)
     }
 }
 
-impl<I> nom::error::ParseError<I> for Error<I> {
+impl<I: Clone> nom::error::ParseError<I> for Error<I> {
     fn from_error_kind(input: I, kind: nom::error::ErrorKind) -> Self {
         Self {
-            kind: ErrorKind::Nom(input, kind),
-            backtrace: Vec::new(),
+            kind: ErrorKind::Nom(input.clone(), kind),
+            input,
         }
     }
 
-    fn append(input: I, kind: nom::error::ErrorKind, mut other: Self) -> Self {
-        other.backtrace.push(Self::from_error_kind(input, kind));
+    fn append(_: I, _: nom::error::ErrorKind, other: Self) -> Self {
         other
     }
 }
@@ -339,7 +922,7 @@ mod tests {
     #[test]
     fn test_no_args_left() {
         let cmd = Command::Quit;
-        let error = Err(Error::custom(ErrorKind::TooManyArguments(cmd.clone())));
+        let error = Err(Error::custom("", ErrorKind::TooManyArguments(cmd.clone())));
 
         // Should succeed
         assert_eq!(no_args_left("", cmd.clone()), Ok(("", cmd.clone())));
@@ -364,11 +947,12 @@ mod tests {
         assert_eq!(target("  ", cmd.clone()), Ok(("", Target::Selected)));
         assert_eq!(target("*", cmd.clone()), Ok(("", Target::All)));
         assert_eq!(target("  * ", cmd.clone()), Ok((" ", Target::All)));
+        assert_eq!(target("@", cmd.clone()), Ok(("", Target::Marked)));
 
         // Should return an error
         assert_eq!(
             target("a *", cmd.clone()),
-            Err(Error::custom(ErrorKind::UnexpectedArgument(CommandKeyword::Run)))
+            Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Run)))
         )
     }
 
@@ -380,13 +964,126 @@ mod tests {
         assert_eq!(filter_arg("+"), Ok(("", Filter::Passed)));
         assert_eq!(filter_arg("-"), Ok(("", Filter::Failed)));
         assert_eq!(filter_arg("~"), Ok(("", Filter::Waiting)));
+        assert_eq!(filter_arg("?"), Ok(("", Filter::Flaky)));
         assert_eq!(filter_arg(" #test "), Ok((" ", Filter::Tag(String::from("test")))));
+        assert_eq!(filter_arg("android"), Ok(("", Filter::Name(String::from("android")))));
+
+        // Expressions
+        let tag = |t: &str| Box::new(Filter::Tag(String::from(t)));
+        assert_eq!(
+            filter_arg("#a or #b and not -"),
+            Ok((
+                "",
+                Filter::Or(
+                    tag("a"),
+                    Box::new(Filter::And(tag("b"), Box::new(Filter::Not(Box::new(Filter::Failed)))))
+                )
+            ))
+        );
+        let expected = Filter::And(
+            Box::new(Filter::Or(tag("net"), tag("db"))),
+            Box::new(Filter::Not(Box::new(Filter::Failed))),
+        );
+        assert_eq!(filter_arg("(#net or #db) and not -"), Ok(("", expected.clone())));
+        assert_eq!(filter_arg("( #net or#db )and not(-)"), Ok(("", expected.clone())));
+        assert_eq!(expected.to_string(), "(#net or #db) and not -");
+
+        // Patterns and fields
+        let field = |field, pattern| Ok(("", Filter::Field(field, pattern)));
+        let regex = |pattern: &str| Pattern::Regex(Matcher::regex(pattern).unwrap());
+        let glob = |glob: &str| Pattern::Glob(Matcher::glob(glob).unwrap());
+        let contains = |text: &str| Pattern::Contains(String::from(text));
+        assert_eq!(filter_arg("/^cli_.*json$/"), field(Field::Name, regex("^cli_.*json$")));
+        assert_eq!(filter_arg("/a\\/b/"), field(Field::Name, regex("a/b")));
+        assert_eq!(filter_arg("cli_*"), field(Field::Name, glob("cli_*")));
+        assert_eq!(filter_arg("cmd:cargo"), field(Field::Cmd, contains("cargo")));
+        assert_eq!(
+            filter_arg("desc:\"network \\\"io\\\"\""),
+            field(Field::Description, contains("network \"io\""))
+        );
+        assert_eq!(filter_arg("stdout:~panic"), field(Field::Stdout, regex("panic")));
+        assert_eq!(
+            filter_arg("stderr:*.rs"),
+            field(Field::Stderr, glob("*.rs"))
+        );
+        assert_eq!(filter_arg("code:!=0"), Ok(("", Filter::Code(Comparison::NotEqual, 0))));
+        assert_eq!(filter_arg("code:2"), Ok(("", Filter::Code(Comparison::Equal, 2))));
+        assert_eq!(
+            filter_arg("cmd:cargo and code:>=1"),
+            Ok((
+                "",
+                Filter::And(
+                    Box::new(Filter::Field(Field::Cmd, contains("cargo"))),
+                    Box::new(Filter::Code(Comparison::GreaterOrEqual, 1))
+                )
+            ))
+        );
+        // Quoted strings
+        let name = |name: &str| Ok(("", Filter::Name(String::from(name))));
+        assert_eq!(filter_arg("\"v1.2 / linux\""), name("v1.2 / linux"));
+        assert_eq!(filter_arg("\"a\\\"b\\\\c\""), name("a\"b\\c"));
+        assert_eq!(filter_arg("\"a\\tb\\nc\""), name("a\tb\nc"));
+        assert_eq!(filter_arg("\"and\""), name("and"));
+        assert_eq!(filter_arg("stdout:~\"\\d+\""), field(Field::Stdout, regex("\\d+")));
+
+        for input in &[
+            "/^cli_/",
+            "desc:\"a b\"",
+            "stdout:/pan\\/ic/",
+            "code:<=3",
+            "cmd:\"~x\"",
+            "\"a b.c\"",
+            "\"and\"",
+            "\"a\\\"b\\tc\"",
+        ] {
+            assert_eq!(filter_arg(input).unwrap().1.to_string(), *input);
+        }
+
+        // Globs match the whole text, newlines included
+        let matcher = Matcher::glob("*pan?c*").unwrap();
+        assert!(matcher.is_match(b"thread 'main'\npanicked at\nsrc/main.rs"));
+        assert!(!matcher.is_match(b"thread 'main'\nexited"));
+        let matcher = Matcher::glob("[!a-c][[]*").unwrap();
+        assert!(matcher.is_match(b"d[\n"));
+        assert!(!matcher.is_match(b"a[\n"));
 
         // Should return an error
         assert_eq!(
             filter_arg("@test"),
-            Err(Error::custom(ErrorKind::UnexpectedArgument(CommandKeyword::Filter)))
+            Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Filter)))
         );
+        let inputs = [
+            "(#net or #db",
+            "#net and",
+            "not",
+            "and",
+            "/(/",
+            "code:x",
+            "desc:\"open",
+            "name:[a-",
+            "[a-",
+            "stdout:[]",
+        ];
+        for input in &inputs {
+            assert_eq!(
+                filter_arg(input),
+                Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Filter)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_repeat_count() {
+        let error = Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Repeat)));
+
+        // Should succeed
+        assert_eq!(repeat_count("5"), Ok(("", 5)));
+        assert_eq!(repeat_count(" 12 *"), Ok((" *", 12)));
+
+        // Should return an error
+        assert_eq!(repeat_count(""), error);
+        assert_eq!(repeat_count(" 0"), error);
+        assert_eq!(repeat_count("*"), error);
     }
 
     #[test]
@@ -422,18 +1119,53 @@ mod tests {
         assert_eq!(commands("run"), Ok(("", vec![Command::Run(Target::Selected)])));
         assert_eq!(commands("run *"), Ok(("", vec![Command::Run(Target::All)])));
         assert_eq!(commands("r*"), Ok(("", vec![Command::Run(Target::All)])));
+        assert_eq!(commands("repeat 3"), Ok(("", vec![Command::Repeat(3, ts.clone())])));
+        assert_eq!(commands("rp 10 *"), Ok(("", vec![Command::Repeat(10, ta.clone())])));
         assert_eq!(commands("show"), Ok(("", vec![Command::Show(Target::Selected)])));
         assert_eq!(commands("s*"), Ok(("", vec![Command::Show(Target::All)])));
         assert_eq!(commands("update"), Ok(("", vec![Command::Update(ts.clone())])));
         assert_eq!(commands("u*"), Ok(("", vec![Command::Update(ta.clone())])));
         assert_eq!(commands("u*;"), Ok(("", vec![Command::Update(ta.clone())])));
         assert_eq!(commands("u;"), Ok(("", vec![Command::Update(ts.clone())])));
+        assert_eq!(commands("s; h"), Ok(("", vec![Command::Show(ts.clone()), Command::Help])));
         assert_eq!(commands("delete"), Ok(("", vec![Command::Delete(ts.clone())])));
         assert_eq!(commands("d*"), Ok(("", vec![Command::Delete(ta.clone())])));
+        assert_eq!(commands("d @"), Ok(("", vec![Command::Delete(Target::Marked)])));
+        assert_eq!(commands("s@"), Ok(("", vec![Command::Show(Target::Marked)])));
+        assert_eq!(commands("m"), Ok(("", vec![Command::Mark])));
+        assert_eq!(commands("unmark"), Ok(("", vec![Command::Unmark])));
+        assert_eq!(commands("pop"), Ok(("", vec![Command::Pop(None)])));
+        assert_eq!(
+            commands("p 2; c"),
+            Ok(("", vec![Command::Pop(Some(2)), Command::Clear]))
+        );
+        assert_eq!(
+            commands("um; u"),
+            Ok(("", vec![Command::Unmark, Command::Update(ts.clone())]))
+        );
+        let add = |tag: &str| TagChange::Add(String::from(tag));
+        let remove = |tag: &str| TagChange::Remove(String::from(tag));
+        assert_eq!(
+            commands("tag +net"),
+            Ok(("", vec![Command::Tag(vec![add("net")], ts.clone())]))
+        );
+        assert_eq!(
+            commands("t +net -slow-io *"),
+            Ok(("", vec![Command::Tag(vec![add("net"), remove("slow-io")], ta.clone())]))
+        );
+        assert_eq!(
+            commands("t-net@; tags"),
+            Ok((
+                "",
+                vec![Command::Tag(vec![remove("net")], Target::Marked), Command::Tags]
+            ))
+        );
+        assert_eq!(commands("ts"), Ok(("", vec![Command::Tags])));
         assert_eq!(commands("filter-"), Ok(("", vec![Command::Filter(Filter::Failed)])));
         assert_eq!(commands("f-"), Ok(("", vec![Command::Filter(Filter::Failed)])));
         assert_eq!(commands("f+"), Ok(("", vec![Command::Filter(Filter::Passed)])));
         assert_eq!(commands("f~"), Ok(("", vec![Command::Filter(Filter::Waiting)])));
+        assert_eq!(commands("f?"), Ok(("", vec![Command::Filter(Filter::Flaky)])));
         assert_eq!(
             commands("f#tag"),
             Ok(("", vec![Command::Filter(Filter::Tag(String::from("tag")))]))
@@ -450,14 +1182,95 @@ mod tests {
         );
 
         // Should return an error
-        assert_eq!(commands("qt"), Err(Error::custom(ErrorKind::UnknownCommand)));
+        assert_eq!(commands("qt"), Err(Error::custom("", ErrorKind::UnknownCommand)));
         assert_eq!(
             commands("quit *"),
-            Err(Error::custom(ErrorKind::TooManyArguments(Command::Quit)))
+            Err(Error::custom("", ErrorKind::TooManyArguments(Command::Quit)))
         );
         assert_eq!(
             commands("run * *"),
-            Err(Error::custom(ErrorKind::TooManyArguments(Command::Run(Target::All))))
+            Err(Error::custom(
+                "",
+                ErrorKind::TooManyArguments(Command::Run(Target::All))
+            ))
+        );
+        assert_eq!(
+            commands("tag"),
+            Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Tag)))
+        );
+        assert_eq!(
+            commands("tag net"),
+            Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Tag)))
+        );
+        assert_eq!(
+            commands("tags +net"),
+            Err(Error::custom("", ErrorKind::TooManyArguments(Command::Tags)))
+        );
+        assert_eq!(
+            commands("repeat *"),
+            Err(Error::custom("", ErrorKind::UnexpectedArgument(CommandKeyword::Repeat)))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("qt"), Err(String::from("Unknown command at column 1\n  qt\n  ^")));
+        assert_eq!(
+            parse("h; run  * *"),
+            Err(String::from(
+                "Too many arguments in run at column 11\n  h; run  * *\n            ^"
+            ))
+        );
+        assert_eq!(
+            parse("f (#net or"),
+            Err(String::from(
+                "Unexpected argument in filter at column 11\n  f (#net or\n            ^"
+            ))
+        );
+        assert_eq!(
+            parse("f \"open"),
+            Err(String::from(
+                "Unexpected argument in filter at column 3\n  f \"open\n    ^"
+            ))
+        );
+        assert_eq!(
+            parse("f #a or\n  (#b and"),
+            Err(String::from(
+                "Unexpected argument in filter at column 10\n    (#b and\n           ^"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_tag_change_from_str() {
+        assert_eq!("+net".parse(), Ok(TagChange::Add(String::from("net"))));
+        assert_eq!("-slow_io".parse(), Ok(TagChange::Remove(String::from("slow_io"))));
+        for input in &["net", "+", "+net -db", "#net", "+net!"] {
+            assert!(input.parse::<TagChange>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_completions() {
+        let names = vec![String::from("net-up"), String::from("net-down"), String::from("disk")];
+        let tags = vec![String::from("net"), String::from("slow")];
+        let complete = |input| get_completions(input, &names, &tags);
+
+        assert_eq!(complete("r"), (0, vec![String::from("repeat"), String::from("run")]));
+        assert_eq!(complete("f #net; sh"), (8, vec![String::from("show")]));
+        assert_eq!(complete("f #"), (2, vec![String::from("#net"), String::from("#slow")]));
+        assert_eq!(
+            complete("filter n"),
+            (7, vec![String::from("net-up"), String::from("net-down")])
+        );
+        assert_eq!(complete("f (#n"), (3, vec![String::from("#net")]));
+        assert_eq!(complete("t -s"), (2, vec![String::from("-slow")]));
+        assert_eq!(
+            complete("tag +net -"),
+            (9, vec![String::from("-net"), String::from("-slow")])
         );
+        assert_eq!(complete("t n"), (2, vec![]));
+        assert_eq!(complete("r *"), (2, vec![]));
+        assert_eq!(complete("x"), (0, vec![]));
     }
 }
@@ -1,14 +1,119 @@
 use std::io::Write;
-use termion::color;
+use std::ops::Range;
+use termion::{color, style, terminal_size};
+use unicode_width::UnicodeWidthChar;
 
-use crate::diff::{get_diff, DiffLine};
+use crate::ansi;
+use crate::data::{sorted_lines, StreamMode};
+use crate::diff::{get_changed_spans, get_diff, get_diff_with, get_hunks, Algorithm, DiffLine, Hunk, Layout, Spans};
+use crate::json::{get_json_diff, parse_paths, Difference};
 use super::theme::Theme;
+use super::BoxedWriter;
 
-/// Writes the diff between two snapshots to buffer.
-pub fn write_diff<B: Write>(old: &Vec<u8>, new: &Vec<u8>, buffer: &mut B, theme: &Theme) {
+/// The width of a side by side diff when it can not be retrieved from the
+/// terminal.
+const DEFAULT_WIDTH: usize = 160;
+/// The minimum terminal width for diffs to be displayed side by side
+/// automatically.
+const MIN_SPLIT_WIDTH: usize = 120;
+/// The minimum width of a side of a side by side diff.
+const MIN_CELL_WIDTH: usize = 16;
+/// The number of columns a tab is expanded to in side by side diffs.
+const TAB_WIDTH: usize = 4;
+
+/// How diffs are displayed.
+pub struct DiffOptions {
+    /// The number of unchanged lines displayed around changes.
+    pub context: usize,
+    pub algorithm: Algorithm,
+    pub layout: Layout,
+}
+
+/// A line displayed on one side of a side by side diff.
+struct SplitLine<'a> {
+    /// Either '-', '+' or ' ' for unchanged lines.
+    sign: char,
+    number: usize,
+    bytes: &'a [u8],
+    spans: &'a [Range<usize>],
+    /// Whether to make escape sequences visible.
+    visible: bool,
+}
+
+/// How a character of a side by side diff is displayed.
+#[derive(Clone, Copy, PartialEq)]
+enum CharStyle {
+    Normal,
+    Highlighted,
+    Mark,
+}
+
+/// Writes why the output of a stream does not satisfy its mode to buffer.
+pub fn write_stream_diff<B: Write>(
+    mode: &StreamMode,
+    old: &[u8],
+    new: &[u8],
+    options: &DiffOptions,
+    buffer: &mut B,
+    theme: &Theme,
+) {
+    match mode {
+        StreamMode::Exact => write_diff(old, new, options, buffer, theme),
+        StreamMode::Ignore => (),
+        StreamMode::Lines => write_lines_diff(old, new, buffer, theme),
+        StreamMode::Contains(text) => {
+            buffer.boxed_write_str(&format!("expected to contain: {}", text), theme).unwrap();
+            write_output(new, buffer, theme);
+        }
+        StreamMode::Regex(pattern) => {
+            buffer.boxed_write_str(&format!("expected to match: {}", pattern), theme).unwrap();
+            write_output(new, buffer, theme);
+        }
+        StreamMode::Json { ignore, tolerance } => {
+            write_json_diff(old, new, ignore, *tolerance, options, buffer, theme)
+        }
+    }
+}
+
+/// Writes the diff between two snapshots to buffer, as hunks of changes
+/// surrounded by `options.context` unchanged lines.
+pub fn write_diff<B: Write>(old: &[u8], new: &[u8], options: &DiffOptions, buffer: &mut B, theme: &Theme) {
     let old_lines: Vec<&[u8]> = old.split(|byte| *byte == b'\n').collect();
     let new_lines: Vec<&[u8]> = new.split(|byte| *byte == b'\n').collect();
-    let diff = get_diff(&old_lines, &new_lines);
+    let diff = get_diff_with(options.algorithm, &old_lines, &new_lines);
+    let split_width = options.layout.split_width();
+    let mut pos = 0;
+    for hunk in get_hunks(&diff, options.context) {
+        if hunk.lines.start > pos {
+            write_collapsed(hunk.lines.start - pos, buffer, theme);
+        }
+        write!(
+            buffer,
+            "{}@@ -{},{} +{},{} @@{}\r\n",
+            theme.blue,
+            hunk.old_start,
+            hunk.old_len,
+            hunk.new_start,
+            hunk.new_len,
+            color::Fg(color::Reset)
+        )
+        .unwrap();
+        match split_width {
+            Some(width) => write_split_hunk(&diff[hunk.lines.clone()], &hunk, width, buffer, theme),
+            None => write_hunk(&diff[hunk.lines.clone()], buffer, theme),
+        }
+        pos = hunk.lines.end;
+    }
+    if pos > 0 && pos < diff.len() {
+        write_collapsed(diff.len() - pos, buffer, theme);
+    }
+}
+
+/// Writes the lines of a hunk.
+/// Escape sequences are made visible in blocks of changes where only they
+/// differ, trailing whitespaces and carriage returns of changed lines are
+/// always made visible.
+fn write_hunk<B: Write>(diff: &[DiffLine], buffer: &mut B, theme: &Theme) {
     // Define colors
     let bg_color = color::Bg(color::Black);
     let bg_reset = color::Bg(color::Reset);
@@ -17,23 +122,449 @@ pub fn write_diff<B: Write>(old: &Vec<u8>, new: &Vec<u8>, buffer: &mut B, theme:
     let fg_red = color::Fg(color::LightRed);
     let fg_reset = color::Fg(color::Reset);
     // Display diff
+    let mut idx = 0;
+    while idx < diff.len() {
+        if let DiffLine::Keep(bytes) = diff[idx] {
+            write!(buffer, "{}│{} ", fg_blue, fg_reset).unwrap();
+            write_line(bytes, false, buffer);
+            write!(buffer, "\r\n").unwrap();
+            idx += 1;
+            continue;
+        }
+        // Gather a block of consecutive changes
+        let block_len = diff[idx..]
+            .iter()
+            .take_while(|line| !matches!(line, DiffLine::Keep(_)))
+            .count();
+        let block = &diff[idx..idx + block_len];
+        let visible = only_escapes_differ(block);
+        let spans = if visible { Vec::new() } else { get_block_spans(block) };
+        let (mut deleted, mut inserted) = (0, 0);
+        for line in block {
+            match line {
+                DiffLine::Delete(bytes) => {
+                    let line_spans = spans.get(deleted).map(|(old, _)| &old[..]).unwrap_or(&[]);
+                    write!(buffer, "{}-{} {}", fg_red, fg_reset, bg_color).unwrap();
+                    write_changed_line(bytes, visible, line_spans, color::Red, buffer);
+                    write!(buffer, "{}\r\n", bg_reset).unwrap();
+                    deleted += 1;
+                }
+                DiffLine::Insert(bytes) => {
+                    let line_spans = spans.get(inserted).map(|(_, new)| &new[..]).unwrap_or(&[]);
+                    write!(buffer, "{}+{} {}", fg_green, fg_reset, bg_color).unwrap();
+                    write_changed_line(bytes, visible, line_spans, color::Green, buffer);
+                    write!(buffer, "{}\r\n", bg_reset).unwrap();
+                    inserted += 1;
+                }
+                DiffLine::Keep(_) => (),
+            }
+        }
+        idx += block_len;
+    }
+}
+
+/// Writes the lines of a hunk in two columns of `width` characters in total,
+/// the old output on the left and the new one on the right.
+fn write_split_hunk<B: Write>(diff: &[DiffLine], hunk: &Hunk, width: usize, buffer: &mut B, theme: &Theme) {
+    let last_line = std::cmp::max(hunk.old_start + hunk.old_len, hunk.new_start + hunk.new_len);
+    let number_width = last_line.to_string().len();
+    // Each side holds a sign, a line number and a cell separated by spaces,
+    // sides are separated by " │ "
+    let cell_width = std::cmp::max((width.saturating_sub(3) / 2).saturating_sub(number_width + 3), MIN_CELL_WIDTH);
+    let (mut old_number, mut new_number) = (hunk.old_start, hunk.new_start);
+    let mut idx = 0;
+    while idx < diff.len() {
+        if let DiffLine::Keep(bytes) = diff[idx] {
+            let old_line = SplitLine {
+                sign: ' ',
+                number: old_number,
+                bytes,
+                spans: &[],
+                visible: false,
+            };
+            let new_line = SplitLine {
+                number: new_number,
+                ..old_line
+            };
+            write_split_row(Some(&old_line), Some(&new_line), number_width, cell_width, buffer, theme);
+            old_number += 1;
+            new_number += 1;
+            idx += 1;
+            continue;
+        }
+        // Gather a block of consecutive changes
+        let block_len = diff[idx..]
+            .iter()
+            .take_while(|line| !matches!(line, DiffLine::Keep(_)))
+            .count();
+        let block = &diff[idx..idx + block_len];
+        let visible = only_escapes_differ(block);
+        let spans = if visible { Vec::new() } else { get_block_spans(block) };
+        let mut old_lines = Vec::new();
+        let mut new_lines = Vec::new();
+        for line in block {
+            match line {
+                DiffLine::Delete(bytes) => {
+                    let spans = spans.get(old_lines.len()).map(|(old, _)| &old[..]).unwrap_or(&[]);
+                    old_lines.push(SplitLine {
+                        sign: '-',
+                        number: old_number,
+                        bytes,
+                        spans,
+                        visible,
+                    });
+                    old_number += 1;
+                }
+                DiffLine::Insert(bytes) => {
+                    let spans = spans.get(new_lines.len()).map(|(_, new)| &new[..]).unwrap_or(&[]);
+                    new_lines.push(SplitLine {
+                        sign: '+',
+                        number: new_number,
+                        bytes,
+                        spans,
+                        visible,
+                    });
+                    new_number += 1;
+                }
+                DiffLine::Keep(_) => (),
+            }
+        }
+        for row in 0..std::cmp::max(old_lines.len(), new_lines.len()) {
+            write_split_row(old_lines.get(row), new_lines.get(row), number_width, cell_width, buffer, theme);
+        }
+        idx += block_len;
+    }
+}
+
+/// Writes a row of a side by side diff, a missing line leaves its side blank.
+fn write_split_row<B: Write>(
+    old: Option<&SplitLine>,
+    new: Option<&SplitLine>,
+    number_width: usize,
+    cell_width: usize,
+    buffer: &mut B,
+    theme: &Theme,
+) {
+    write_split_line(old, number_width, cell_width, buffer);
+    write!(buffer, " {}│{} ", theme.blue, color::Fg(color::Reset)).unwrap();
+    write_split_line(new, number_width, cell_width, buffer);
+    write!(buffer, "\r\n").unwrap();
+}
+
+/// Writes one side of a row of a side by side diff, long lines are truncated
+/// with `…`.
+fn write_split_line<B: Write>(line: Option<&SplitLine>, number_width: usize, cell_width: usize, buffer: &mut B) {
+    let line = match line {
+        Some(line) => line,
+        None => {
+            write!(buffer, "{:width$}", "", width = number_width + cell_width + 3).unwrap();
+            return;
+        }
+    };
+    let (fg_color, highlight) = match line.sign {
+        '-' => (color::Fg(color::LightRed).to_string(), color::Bg(color::Red).to_string()),
+        '+' => (color::Fg(color::LightGreen).to_string(), color::Bg(color::Green).to_string()),
+        _ => (color::Fg(color::Reset).to_string(), String::new()),
+    };
+    let changed = line.sign != ' ';
+    let bg_color = if changed { color::Bg(color::Black).to_string() } else { String::new() };
+    write!(
+        buffer,
+        "{}{} {:>width$}{} {}",
+        fg_color,
+        line.sign,
+        line.number,
+        color::Fg(color::Reset),
+        bg_color,
+        width = number_width
+    )
+    .unwrap();
+    let mut chars = get_split_chars(line);
+    let char_width = |(c, _): &(char, CharStyle)| c.width().unwrap_or(0);
+    let mut width: usize = chars.iter().map(char_width).sum();
+    if width > cell_width {
+        // Leave room for the ellipsis, a wide character may leave a blank column
+        let mut len = 0;
+        width = 1;
+        while width + char_width(&chars[len]) <= cell_width {
+            width += char_width(&chars[len]);
+            len += 1;
+        }
+        chars.truncate(len);
+        chars.push(('…', CharStyle::Mark));
+    }
+    let mut current = CharStyle::Normal;
+    for (c, style) in &chars {
+        if *style != current {
+            match style {
+                CharStyle::Normal => write!(buffer, "{}{}", color::Fg(color::Reset), bg_color).unwrap(),
+                CharStyle::Highlighted => write!(buffer, "{}", highlight).unwrap(),
+                CharStyle::Mark => write!(buffer, "{}{}", color::Fg(color::Yellow), bg_color).unwrap(),
+            }
+            current = *style;
+        }
+        write!(buffer, "{}", c).unwrap();
+    }
+    write!(
+        buffer,
+        "{}{}{:width$}",
+        color::Fg(color::Reset),
+        color::Bg(color::Reset),
+        "",
+        width = cell_width - width
+    )
+    .unwrap();
+}
+
+/// Returns the characters displayed for a line of a side by side diff.
+/// Escape sequences are removed or made visible, tabs are expanded and the
+/// trailing whitespaces of changed lines are marked.
+fn get_split_chars(line: &SplitLine) -> Vec<(char, CharStyle)> {
+    let text = if line.visible {
+        String::from_utf8_lossy(&ansi::make_visible(line.bytes)).into_owned()
+    } else {
+        String::from_utf8_lossy(&ansi::strip(line.bytes)).into_owned()
+    };
+    // Spans are byte offsets of the original line
+    let spans = if text.len() == line.bytes.len() { line.spans } else { &[] };
+    let changed = line.sign != ' ';
+    let content_len = if changed {
+        text.trim_end_matches([' ', '\t', '\r']).len()
+    } else {
+        text.len()
+    };
+    let mut chars = Vec::with_capacity(text.len());
+    for (idx, c) in text.char_indices() {
+        if idx >= content_len {
+            let mark = match c {
+                ' ' => '·',
+                '\t' => '→',
+                _ => '␍',
+            };
+            chars.push((mark, CharStyle::Mark));
+            continue;
+        }
+        let style = if spans.iter().any(|span| span.contains(&idx)) {
+            CharStyle::Highlighted
+        } else {
+            CharStyle::Normal
+        };
+        match c {
+            '\t' => chars.resize(chars.len() + TAB_WIDTH, (' ', style)),
+            c if c.is_control() => (),
+            c => chars.push((c, style)),
+        }
+    }
+    chars
+}
+
+/// Writes the marker of a region of unchanged lines.
+fn write_collapsed<B: Write>(count: usize, buffer: &mut B, theme: &Theme) {
+    let plural = if count > 1 { "s" } else { "" };
+    write!(
+        buffer,
+        "{}⋯{} {} unchanged line{}\r\n",
+        theme.blue,
+        color::Fg(color::Reset),
+        format_count(count),
+        plural
+    )
+    .unwrap();
+}
+
+impl Layout {
+    /// Returns the width of side by side diffs, or None if diffs should be
+    /// unified.
+    fn split_width(&self) -> Option<usize> {
+        let width = terminal_size().ok().map(|(width, _)| width as usize);
+        match self {
+            Layout::Auto => width.filter(|width| *width >= MIN_SPLIT_WIDTH),
+            Layout::Unified => None,
+            Layout::Split => Some(width.unwrap_or(DEFAULT_WIDTH)),
+        }
+    }
+}
+
+/// Formats a count with thousands separators, for instance `4,812`.
+fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+    for (idx, digit) in digits.chars().enumerate() {
+        if idx > 0 && idx % 3 == digits.len() % 3 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Writes a line of output, escape sequences are either made visible or
+/// followed by a style reset so that they do not leak out of the line.
+fn write_line<B: Write>(bytes: &[u8], visible: bool, buffer: &mut B) {
+    if visible {
+        buffer.write_all(&ansi::make_visible(bytes)).unwrap();
+    } else {
+        buffer.write_all(bytes).unwrap();
+        if ansi::has_escapes(bytes) {
+            write!(buffer, "{}", style::Reset).unwrap();
+        }
+    }
+}
+
+/// Pairs the n-th deleted line of a block of changes with its n-th inserted
+/// line, and returns the changed spans of each pair.
+/// Pairs of lines that are too different, or that contain escape sequences,
+/// have no spans.
+fn get_block_spans(block: &[DiffLine]) -> Vec<Spans> {
+    let deleted = block.iter().filter_map(|line| match line {
+        DiffLine::Delete(bytes) => Some(*bytes),
+        _ => None,
+    });
+    let inserted = block.iter().filter_map(|line| match line {
+        DiffLine::Insert(bytes) => Some(*bytes),
+        _ => None,
+    });
+    deleted
+        .zip(inserted)
+        .map(|(old, new)| {
+            if ansi::has_escapes(old) || ansi::has_escapes(new) {
+                return (Vec::new(), Vec::new());
+            }
+            get_changed_spans(old, new).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Writes a changed line, the changed spans are highlighted with the `highlight`
+/// background and its trailing whitespaces are marked: `·` for a space, `→`
+/// for a tab and `␍` for a carriage return.
+fn write_changed_line<B: Write, C: color::Color + Copy>(
+    bytes: &[u8],
+    visible: bool,
+    spans: &[Range<usize>],
+    highlight: C,
+    buffer: &mut B,
+) {
+    let content_len = bytes.len()
+        - bytes
+            .iter()
+            .rev()
+            .take_while(|byte| matches!(byte, b' ' | b'\t' | b'\r'))
+            .count();
+    let (content, trailing) = bytes.split_at(content_len);
+    if spans.is_empty() {
+        write_line(content, visible, buffer);
+    } else {
+        let mut pos = 0;
+        for span in spans {
+            let start = std::cmp::min(span.start, content_len);
+            let end = std::cmp::min(span.end, content_len);
+            buffer.write_all(&content[pos..start]).unwrap();
+            write!(buffer, "{}", color::Bg(highlight)).unwrap();
+            buffer.write_all(&content[start..end]).unwrap();
+            write!(buffer, "{}", color::Bg(color::Black)).unwrap();
+            pos = end;
+        }
+        buffer.write_all(&content[pos..]).unwrap();
+    }
+    if trailing.is_empty() {
+        return;
+    }
+    write!(buffer, "{}", color::Fg(color::Yellow)).unwrap();
+    for byte in trailing {
+        let mark = match byte {
+            b' ' => "·",
+            b'\t' => "→",
+            _ => "␍",
+        };
+        write!(buffer, "{}", mark).unwrap();
+    }
+    write!(buffer, "{}", color::Fg(color::Reset)).unwrap();
+}
+
+/// Returns true if the deleted and inserted lines of a block of changes are
+/// the same once escape sequences are removed.
+fn only_escapes_differ(block: &[DiffLine]) -> bool {
+    let mut deleted = Vec::new();
+    let mut inserted = Vec::new();
+    for line in block {
+        match line {
+            DiffLine::Delete(bytes) => deleted.push(ansi::strip(bytes)),
+            DiffLine::Insert(bytes) => inserted.push(ansi::strip(bytes)),
+            DiffLine::Keep(_) => (),
+        }
+    }
+    !deleted.is_empty() && deleted == inserted
+}
+
+/// Writes the lines missing from, or added to, an output regardless of their
+/// order.
+fn write_lines_diff<B: Write>(old: &[u8], new: &[u8], buffer: &mut B, theme: &Theme) {
+    let old_lines = sorted_lines(old);
+    let new_lines = sorted_lines(new);
+    let diff = get_diff(&old_lines, &new_lines);
+    let bg_color = color::Bg(color::Black);
+    let bg_reset = color::Bg(color::Reset);
+    let fg_green = color::Fg(color::LightGreen);
+    let fg_red = color::Fg(color::LightRed);
+    let fg_reset = color::Fg(color::Reset);
+    buffer.boxed_write_str("lines differ (in any order):", theme).unwrap();
     for line in diff {
         match line {
-            DiffLine::Keep(bytes) => {
-                write!(buffer, "{}│{} ", fg_blue, fg_reset).unwrap();
-                buffer.write_all(bytes).unwrap();
-                write!(buffer, "\r\n").unwrap();
-            }
+            DiffLine::Keep(_) => (),
             DiffLine::Delete(bytes) => {
                 write!(buffer, "{}-{} {}", fg_red, fg_reset, bg_color).unwrap();
-                buffer.write_all(bytes).unwrap();
+                write_changed_line(bytes, false, &[], color::Red, buffer);
                 write!(buffer, "{}\r\n", bg_reset).unwrap();
             }
             DiffLine::Insert(bytes) => {
                 write!(buffer, "{}+{} {}", fg_green, fg_reset, bg_color).unwrap();
-                buffer.write_all(bytes).unwrap();
+                write_changed_line(bytes, false, &[], color::Green, buffer);
                 write!(buffer, "{}\r\n", bg_reset).unwrap();
             }
         }
     }
 }
+
+/// Writes the path of the values that changed between two JSON outputs, falls
+/// back to a line diff if any of the outputs is not valid JSON.
+fn write_json_diff<B: Write>(
+    old: &[u8],
+    new: &[u8],
+    ignore: &[String],
+    tolerance: f64,
+    options: &DiffOptions,
+    buffer: &mut B,
+    theme: &Theme,
+) {
+    let (old_json, new_json) = match (serde_json::from_slice(old), serde_json::from_slice(new)) {
+        (Ok(old_json), Ok(new_json)) => (old_json, new_json),
+        (Err(err), _) | (_, Err(err)) => {
+            buffer.boxed_write_str(&format!("invalid JSON: {}", err), theme).unwrap();
+            return write_diff(old, new, options, buffer, theme);
+        }
+    };
+    let fg_yellow = color::Fg(color::LightYellow);
+    let fg_green = color::Fg(color::LightGreen);
+    let fg_red = color::Fg(color::LightRed);
+    let fg_reset = color::Fg(color::Reset);
+    for difference in get_json_diff(&old_json, &new_json, &parse_paths(ignore), tolerance) {
+        match difference {
+            Difference::Changed(path, old_value, new_value) => {
+                write!(buffer, "{}~{} {}: {} → {}\r\n", fg_yellow, fg_reset, path, old_value, new_value).unwrap()
+            }
+            Difference::Removed(path, old_value) => {
+                write!(buffer, "{}-{} {}: {}\r\n", fg_red, fg_reset, path, old_value).unwrap()
+            }
+            Difference::Added(path, new_value) => {
+                write!(buffer, "{}+{} {}: {}\r\n", fg_green, fg_reset, path, new_value).unwrap()
+            }
+        }
+    }
+}
+
+/// Writes the actual output of a stream.
+fn write_output<B: Write>(new: &[u8], buffer: &mut B, theme: &Theme) {
+    buffer.boxed_write_str("got:", theme).unwrap();
+    buffer.boxed_write(new, theme).unwrap();
+}
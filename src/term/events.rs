@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::io::{Read, Stdin};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use termion::event::{parse_event, Event, Key};
+
+/// Something happening on the terminal.
+pub enum TermEvent {
+    Key(Key),
+    Resize,
+}
+
+/// Reads keys from stdin, and notifies terminal resizes.
+pub struct Events {
+    stdin: Stdin,
+    /// Keys read but not handled yet
+    keys: VecDeque<Key>,
+    /// The start of an escape sequence or of a char split across reads
+    pending: Vec<u8>,
+    /// Receives a byte each time the terminal is resized
+    #[cfg(unix)]
+    resize: UnixStream,
+    /// The SIGWINCH handler writing to `resize`
+    #[cfg(unix)]
+    signal: signal_hook::SigId,
+}
+
+impl Events {
+    #[cfg(unix)]
+    pub fn new(stdin: Stdin) -> Events {
+        let (resize, resize_sender) = UnixStream::pair().unwrap();
+        resize.set_nonblocking(true).unwrap();
+        let signal = signal_hook::low_level::pipe::register(signal_hook::consts::SIGWINCH, resize_sender).unwrap();
+        Events {
+            stdin,
+            keys: VecDeque::new(),
+            pending: Vec::new(),
+            resize,
+            signal,
+        }
+    }
+
+    /// Resizes are not notified, the terminal size is read again after each
+    /// command instead.
+    #[cfg(not(unix))]
+    pub fn new(stdin: Stdin) -> Events {
+        Events {
+            stdin,
+            keys: VecDeque::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Waits for the next key press or terminal resize, returns None once
+    /// stdin is closed.
+    #[cfg(unix)]
+    pub fn next_event(&mut self) -> Option<TermEvent> {
+        loop {
+            if let Some(key) = self.keys.pop_front() {
+                return Some(TermEvent::Key(key));
+            }
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.stdin.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: self.resize.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            // Safe: the descriptors outlive the call
+            if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+                continue; // Interrupted by a signal
+            }
+            if fds[1].revents != 0 {
+                let mut buffer = [0; 64];
+                while let Ok(n) = self.resize.read(&mut buffer) {
+                    if n == 0 {
+                        break;
+                    }
+                }
+                return Some(TermEvent::Resize);
+            }
+            if fds[0].revents != 0 {
+                self.read_keys()?;
+            }
+        }
+    }
+
+    /// Waits for the next key press, returns None once stdin is closed.
+    #[cfg(not(unix))]
+    pub fn next_event(&mut self) -> Option<TermEvent> {
+        loop {
+            if let Some(key) = self.keys.pop_front() {
+                return Some(TermEvent::Key(key));
+            }
+            self.read_keys()?;
+        }
+    }
+
+    /// Reads the available input and parses it into keys. Stdin is read
+    /// directly rather than through its buffer, so that no input is left
+    /// behind when polling it.
+    #[cfg(unix)]
+    fn read_keys(&mut self) -> Option<()> {
+        let mut buffer = [0u8; 1024];
+        // Safe: the buffer is valid for its whole length
+        let n = unsafe {
+            libc::read(
+                self.stdin.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if n <= 0 {
+            return None;
+        }
+        self.parse_keys(&buffer[..n as usize], (n as usize) < buffer.len());
+        Some(())
+    }
+
+    /// Reads the next input and parses it into keys.
+    #[cfg(not(unix))]
+    fn read_keys(&mut self) -> Option<()> {
+        let mut buffer = [0u8; 1024];
+        let n = self.stdin.read(&mut buffer).ok().filter(|n| *n > 0)?;
+        self.parse_keys(&buffer[..n], n < buffer.len());
+        Some(())
+    }
+
+    /// Parses the input read after the pending bytes into keys. Sequences cut
+    /// by the end of the input are kept for the next read, but a lone escape
+    /// ending a `complete` input is the Esc key.
+    fn parse_keys(&mut self, input: &[u8], complete: bool) {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(input);
+        let mut start = 0;
+        while start < bytes.len() {
+            if complete && bytes[start..] == [b'\x1B'] {
+                self.keys.push_back(Key::Esc);
+                return;
+            }
+            let mut rest = bytes[start + 1..].iter();
+            let mut cut = false;
+            let event = parse_event(
+                bytes[start],
+                &mut std::iter::from_fn(|| match rest.next() {
+                    Some(byte) => Some(Ok(*byte)),
+                    None => {
+                        cut = true;
+                        None
+                    }
+                }),
+            );
+            match event {
+                Ok(Event::Key(key)) => self.keys.push_back(key),
+                Err(_) if cut => {
+                    self.pending = bytes[start..].to_vec();
+                    return;
+                }
+                // Invalid or unsupported sequences are skipped
+                _ => (),
+            }
+            start = bytes.len() - rest.as_slice().len();
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Events {
+    fn drop(&mut self) {
+        signal_hook::low_level::unregister(self.signal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        let mut events = Events::new(std::io::stdin());
+        events.parse_keys(b"a\x1B[", true);
+        assert_eq!(events.pending, b"\x1B[");
+        events.parse_keys(b"Ab\xC3", true);
+        assert_eq!(events.pending, b"\xC3");
+        events.parse_keys(b"\xA9\x1B", true);
+        assert!(events.pending.is_empty());
+        let keys: Vec<Key> = events.keys.drain(..).collect();
+        assert_eq!(keys, vec![Key::Char('a'), Key::Up, Key::Char('b'), Key::Char('é'), Key::Esc]);
+
+        // The escape may start a sequence if the read was cut
+        events.parse_keys(b"\x1B", false);
+        assert_eq!(events.pending, b"\x1B");
+        events.parse_keys(b"OP", true);
+        assert_eq!(events.keys.pop_front(), Some(Key::F(1)));
+    }
+}
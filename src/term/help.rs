@@ -20,9 +20,19 @@ pub fn write_help<B: Write>(buffer: &mut B) {
         {b}│{rc} {bold}edit    e{rs}  Edit the name or description                        {b}│{rc}\r\n\
         {b}│{rc} {bold}filter  f{rs}  Filter by name (contains) or by #tag (exact match)  {b}│{rc}\r\n\
         {b}│{rc} {bold}help    h{rs}  Print this help                                     {b}│{rc}\r\n\
+        {b}│{rc} {bold}mark    m{rs}  Mark all tests, Space marks the selected one        {b}│{rc}\r\n\
+        {b}│{rc} {bold}pop     p{rs}  Remove the latest filter, or the Nth one            {b}│{rc}\r\n\
         {b}│{rc} {bold}quit    q{rs}  Exit from Parrot REPL                               {b}│{rc}\r\n\
+        {b}│{rc} {bold}repeat  rp{rs} Run N times and report flaky tests                  {b}│{rc}\r\n\
         {b}│{rc} {bold}run     r{rs}  Run the selected test, or all tests by passing '*'  {b}│{rc}\r\n\
         {b}│{rc} {bold}show    s{rs}  Show the selected test, or all tests by passing '*' {b}│{rc}\r\n\
+        {b}│{rc} {bold}tag     t{rs}  Add (+tag) or remove (-tag) tags, '*' for all tests {b}│{rc}\r\n\
+        {b}│{rc} {bold}tags    ts{rs} List tags with their passed and failed counts       {b}│{rc}\r\n\
+        {b}│{rc} {bold}unmark  um{rs} Unmark all tests                                    {b}│{rc}\r\n\
+        {b}│{rc}                                                                {b}│{rc}\r\n\
+        {b}│{rc} Pass '@' instead of '*' to target the marked tests             {b}│{rc}\r\n\
+        {b}│{rc} Combine filters with and, or, not and parentheses              {b}│{rc}\r\n\
+        {b}│{rc} Match with /regex/, globs or fields: f cmd:cargo, f code:!=0   {b}│{rc}\r\n\
         {b}└──{g}──────{y}──────────{r}────────────────────────────{y}──────────{g}──────{b}──┘{rc}\r\n\
         ",
         bold = bold,
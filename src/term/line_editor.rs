@@ -0,0 +1,198 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// An editable line of text, with a cursor.
+#[derive(Default)]
+pub struct LineEditor {
+    pub text: String,
+    /// The byte offset of the cursor, always on a char boundary
+    pub cursor: usize,
+}
+
+impl LineEditor {
+    /// Replaces the text, the cursor is moved to the end of the line.
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// Takes the text out of the editor, leaving it empty.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    /// Replaces the text between `start` and the cursor.
+    pub fn replace_before(&mut self, start: usize, text: &str) {
+        self.text.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    /// Inserts a char at the cursor.
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Deletes the char before the cursor.
+    pub fn delete_before(&mut self) {
+        let start = self.previous_boundary(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes the char under the cursor.
+    pub fn delete_after(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Deletes the word before the cursor, along with the whitespaces
+    /// following it.
+    pub fn delete_word_before(&mut self) {
+        let start = self.word_start();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes everything before the cursor.
+    pub fn delete_to_start(&mut self) {
+        self.text.replace_range(..self.cursor, "");
+        self.cursor = 0;
+    }
+
+    /// Deletes everything after the cursor.
+    pub fn delete_to_end(&mut self) {
+        self.text.truncate(self.cursor);
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.previous_boundary(self.cursor);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary(self.cursor);
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    /// Moves the cursor to the end of the current or next word.
+    pub fn word_right(&mut self) {
+        let rest = &self.text[self.cursor..];
+        let word = rest.len() - rest.trim_start().len();
+        let end = rest[word..]
+            .find(char::is_whitespace)
+            .map_or(rest.len(), |end| word + end);
+        self.cursor += end;
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Returns the number of terminal columns taken by the text before the
+    /// cursor.
+    pub fn cursor_width(&self) -> usize {
+        self.text[..self.cursor].width()
+    }
+
+    /// Returns the start of the current or previous word.
+    fn word_start(&self) -> usize {
+        let before = self.text[..self.cursor].trim_end();
+        before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(start, c)| start + c.len_utf8())
+    }
+
+    /// Returns the offset of the char before `idx`, zero-width chars such as
+    /// combining accents are skipped along with the char they modify.
+    fn previous_boundary(&self, idx: usize) -> usize {
+        self.text[..idx]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.width() != Some(0))
+            .map_or(0, |(start, _)| start)
+    }
+
+    /// Returns the offset of the char after `idx`, skipping zero-width chars.
+    fn next_boundary(&self, idx: usize) -> usize {
+        self.text[idx..]
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| c.width() != Some(0))
+            .map_or(self.text.len(), |(offset, _)| idx + offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an editor holding `text`, with the cursor at `cursor`.
+    fn editor(text: &str, cursor: usize) -> LineEditor {
+        LineEditor {
+            text: String::from(text),
+            cursor,
+        }
+    }
+
+    #[test]
+    fn test_delete_word_before() {
+        let mut input = editor("f #net  r *", 8);
+        input.delete_word_before();
+        assert_eq!((input.text.as_str(), input.cursor), ("f r *", 2));
+        input.delete_word_before();
+        assert_eq!((input.text.as_str(), input.cursor), ("r *", 0));
+        input.delete_word_before();
+        assert_eq!((input.text.as_str(), input.cursor), ("r *", 0));
+    }
+
+    #[test]
+    fn test_word_moves() {
+        let mut input = editor("f  #net r", 0);
+        input.word_right();
+        assert_eq!(input.cursor, 1);
+        input.word_right();
+        assert_eq!(input.cursor, 7);
+        input.word_right();
+        assert_eq!(input.cursor, 9);
+        input.word_right();
+        assert_eq!(input.cursor, 9);
+        input.word_left();
+        assert_eq!(input.cursor, 8);
+        input.word_left();
+        assert_eq!(input.cursor, 3);
+        input.word_left();
+        assert_eq!(input.cursor, 0);
+    }
+
+    #[test]
+    fn test_multi_byte_chars() {
+        // An accent combined with its letter is deleted along with it
+        let mut input = editor("f #cafe\u{301}é", 0);
+        input.end();
+        input.delete_before();
+        assert_eq!(input.text, "f #cafe\u{301}");
+        input.delete_before();
+        assert_eq!(input.text, "f #caf");
+        input.left();
+        input.delete_after();
+        assert_eq!((input.text.as_str(), input.cursor), ("f #ca", 5));
+
+        // Wide chars take two columns
+        let mut input = editor("s 漢字", 0);
+        input.end();
+        assert_eq!(input.cursor_width(), 6);
+        input.left();
+        assert_eq!(input.cursor_width(), 4);
+        input.insert('a');
+        assert_eq!((input.text.as_str(), input.cursor_width()), ("s 漢a字", 5));
+    }
+}
@@ -1,18 +1,47 @@
 use std::io;
 use std::io::{stdin, stdout, Write};
 use termion::{color, style};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+use crate::ansi;
+use crate::data::{AnsiMode, ExpectedCode, Snapshot, StreamMode, Whitespace};
+use crate::driver::{TagStats, View};
 use crate::error::{wrap, Error};
 
 mod diff;
+mod events;
 pub mod help;
+mod line_editor;
+mod plain;
 mod repl;
 mod theme;
+mod tui;
 
-pub use diff::write_diff;
+pub use diff::{write_stream_diff, DiffOptions};
+pub use plain::Plain;
 pub use repl::Input;
 pub use repl::Repl;
 pub use theme::Theme;
+pub use tui::Tui;
+
+/// An interactive interface, commands write their outputs through it.
+pub trait Console {
+    /// Suspends the interface, so that outputs can be written.
+    fn suspend(&mut self);
+
+    /// Writes a single line to the output.
+    fn writeln(&mut self, message: &str);
+
+    /// Writes an output that may be too long to be displayed at once.
+    fn page(&mut self, output: &[u8]);
+
+    /// Offers to read again an output that has already been written, when it
+    /// is too long to be displayed at once.
+    fn review(&mut self, output: &[u8]);
+
+    /// Returns the output stream.
+    fn output(&mut self) -> &mut dyn Write;
+}
 
 pub enum SeparatorKind {
     Top,
@@ -21,6 +50,12 @@ pub enum SeparatorKind {
     _Standalone,
 }
 
+/// Returns true if both stdin and stdout are terminals, false if either is
+/// piped or redirected, in which case there is no interactive interface.
+pub fn is_interactive() -> bool {
+    termion::is_tty(&stdin()) && termion::is_tty(&stdout())
+}
+
 /// Writes a single line to the buffer.
 pub fn writeln<B: Write>(message: &str, buffer: &mut B) {
     write!(buffer, "{}\r\n", message).unwrap();
@@ -45,18 +80,18 @@ pub fn binary_qestion(question: &str) -> Result<bool, Error> {
     }
 }
 
-pub fn snap_preview<B: Write>(snap: &std::process::Output, buffer: &mut B, theme: &Theme) {
+pub fn snap_preview<B: Write>(
+    snap: &std::process::Output,
+    signal: Option<i32>,
+    core_dumped: bool,
+    buffer: &mut B,
+    theme: &Theme,
+) {
     box_separator("status code", SeparatorKind::Top, buffer, theme);
-    let exit_code = snap.status.code();
-    if let Some(code) = exit_code {
-        buffer
-            .boxed_write_str(&format!("{}{}{}", style::Bold, code, style::Reset), theme)
-            .unwrap();
-    } else {
-        buffer
-            .boxed_write_str(&format!("{}None{}", style::Bold, style::Reset), theme)
-            .unwrap();
-    }
+    let status = format_status(snap.status.code(), signal, core_dumped);
+    buffer
+        .boxed_write_str(&format!("{}{}{}", style::Bold, status, style::Reset), theme)
+        .unwrap();
     if snap.stdout.len() > 0 {
         box_separator("stdout", SeparatorKind::Middle, buffer, theme);
         buffer.boxed_write(&snap.stdout, theme).unwrap();
@@ -69,19 +104,13 @@ pub fn snap_preview<B: Write>(snap: &std::process::Output, buffer: &mut B, theme
 }
 
 /// Writes a summary of a given snapshot.
-pub fn snap_summary<B: Write>(
-    description: Option<&String>,
-    cmd: &str,
-    status_code: Option<i32>,
-    buffer: &mut B,
-    theme: &Theme,
-) {
+pub fn snap_summary<B: Write>(snap: &Snapshot, buffer: &mut B, theme: &Theme) {
     let bold = style::Bold;
     let reset = style::Reset;
-    let code = if let Some(code) = status_code {
-        format!("{}", code)
-    } else {
-        String::from("None")
+    let code = format_status(snap.exit_code, snap.signal, snap.core_dumped);
+    let code = match snap.expected_code {
+        ExpectedCode::Exact => code,
+        ref expected => format!("{} (recorded {})", expected, code),
     };
     buffer
         .boxed_write_str(
@@ -89,16 +118,73 @@ pub fn snap_summary<B: Write>(
                 "\
             cmd:  {}{}{}\n\
             code: {}{}{}",
-                bold, cmd, reset, bold, code, reset
+                bold, snap.cmd, reset, bold, code, reset
             ),
             theme,
         )
         .unwrap();
-    if let Some(description) = description {
+    if snap.ansi != AnsiMode::Keep {
+        buffer
+            .boxed_write_str(&format!("ansi: {}{}{}", bold, snap.ansi, reset), theme)
+            .unwrap();
+    }
+    if snap.whitespace != Whitespace::default() {
+        buffer
+            .boxed_write_str(&format!("whitespace: {}{}{}", bold, snap.whitespace, reset), theme)
+            .unwrap();
+    }
+    for (stream, mode) in &[("stdout", &snap.stdout_mode), ("stderr", &snap.stderr_mode)] {
+        if **mode != StreamMode::Exact {
+            buffer
+                .boxed_write_str(&format!("{}: {}{}{}", stream, bold, mode, reset), theme)
+                .unwrap();
+        }
+    }
+    if let Some(description) = &snap.description {
         buffer.boxed_write_str(&format!("\n{}\n", description), theme).unwrap();
     }
 }
 
+/// Formats an exit status code, followed by the signal that terminated the
+/// process if any, for instance "None (signal 11 SIGSEGV, core dumped)".
+pub fn format_status(code: Option<i32>, signal: Option<i32>, core_dumped: bool) -> String {
+    let mut status = match code {
+        Some(code) => format!("{}", code),
+        None => String::from("None"),
+    };
+    if let Some(signal) = signal {
+        status.push_str(&format!(" (signal {}", signal));
+        if let Some(name) = signal_name(signal) {
+            status.push_str(&format!(" {}", name));
+        }
+        if core_dumped {
+            status.push_str(", core dumped");
+        }
+        status.push(')');
+    }
+    status
+}
+
+/// Returns the name of the most common signals, those share the same number
+/// across unix platforms.
+fn signal_name(signal: i32) -> Option<&'static str> {
+    match signal {
+        1 => Some("SIGHUP"),
+        2 => Some("SIGINT"),
+        3 => Some("SIGQUIT"),
+        4 => Some("SIGILL"),
+        5 => Some("SIGTRAP"),
+        6 => Some("SIGABRT"),
+        8 => Some("SIGFPE"),
+        9 => Some("SIGKILL"),
+        11 => Some("SIGSEGV"),
+        13 => Some("SIGPIPE"),
+        14 => Some("SIGALRM"),
+        15 => Some("SIGTERM"),
+        _ => None,
+    }
+}
+
 /// Writes the success message.
 pub fn success<B: Write>(buffer: &mut B) {
     write!(
@@ -125,6 +211,55 @@ pub fn failure<B: Write>(buffer: &mut B) {
     .unwrap();
 }
 
+/// Writes how many snapshots are stable or flaky after repeated runs.
+pub fn repeat_summary<B: Write>(passed: usize, failed: usize, flaky: usize, buffer: &mut B) {
+    write!(
+        buffer,
+        "{}{} stable-pass{}, {}{} stable-fail{}, {}{} flaky{}\r\n",
+        color::Fg(color::LightGreen),
+        passed,
+        color::Fg(color::Reset),
+        color::Fg(color::LightRed),
+        failed,
+        color::Fg(color::Reset),
+        color::Fg(color::LightYellow),
+        flaky,
+        color::Fg(color::Reset)
+    )
+    .unwrap();
+}
+
+/// Writes the number of snapshots of each tag, by status.
+pub fn tag_stats<B: Write>(stats: &[TagStats], buffer: &mut B) {
+    if stats.is_empty() {
+        writeln("No tags.", buffer);
+        return;
+    }
+    let tags: Vec<String> = stats.iter().map(|stats| format!("#{}", stats.tag)).collect();
+    let width = tags.iter().map(|tag| tag.width()).max().unwrap_or(0);
+    let total_width = stats.iter().map(|stats| stats.total.to_string().len()).max().unwrap_or(0);
+    for (tag, stats) in tags.iter().zip(stats) {
+        write!(
+            buffer,
+            "{}{} {:>total_width$} total: {}{} passed{}, {}{} failed{}, {}{} flaky{}\r\n",
+            tag,
+            " ".repeat(width - tag.width()),
+            stats.total,
+            color::Fg(color::LightGreen),
+            stats.passed,
+            color::Fg(color::Reset),
+            color::Fg(color::LightRed),
+            stats.failed,
+            color::Fg(color::Reset),
+            color::Fg(color::LightYellow),
+            stats.flaky,
+            color::Fg(color::Reset),
+            total_width = total_width,
+        )
+        .unwrap();
+    }
+}
+
 /// Draws a separator for boxed messages.
 pub fn box_separator<B: Write>(title: &str, kind: SeparatorKind, buffer: &mut B, theme: &Theme) {
     let corner = match kind {
@@ -160,6 +295,10 @@ impl<W: Write> BoxedWriter for W {
         for line in buf.split(|c| c == &b'\n') {
             write!(self, "{}│{} ", colorize, reset_color)?;
             self.write_all(line)?;
+            // Prevents the output's styles from leaking into the box
+            if ansi::has_escapes(line) {
+                write!(self, "{}", style::Reset)?;
+            }
             self.write_all(&[b'\n', b'\r'])?;
         }
         Ok(())
@@ -174,3 +313,29 @@ impl<W: Write> BoxedWriter for W {
         Ok(())
     }
 }
+
+/// Truncates a text to at most `width` columns.
+fn truncate(text: &str, width: usize) -> &str {
+    let mut columns = 0;
+    for (idx, c) in text.char_indices() {
+        columns += c.width().unwrap_or(0);
+        if columns > width {
+            return &text[..idx];
+        }
+    }
+    text
+}
+
+/// Pads a text with spaces up to `width` columns.
+fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
+
+/// Formats the filter stack of a view, each filter is numbered for `pop`.
+fn format_filters(view: &View) -> String {
+    let mut filters = String::new();
+    for (idx, filter) in view.get_filters().iter().enumerate() {
+        filters.push_str(&format!("  [{}] {}", idx + 1, filter));
+    }
+    filters
+}
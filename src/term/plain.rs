@@ -0,0 +1,39 @@
+use std::io::{Stdout, Write};
+
+use super::Console;
+use crate::ansi::StripWriter;
+
+/// A console for non-interactive outputs, such as pipes or CI logs. Outputs
+/// are written as plain lines, without colors nor raw mode.
+pub struct Plain {
+    stdout: StripWriter<Stdout>,
+}
+
+impl Plain {
+    pub fn new(stdout: Stdout) -> Plain {
+        Plain {
+            stdout: StripWriter::new(stdout),
+        }
+    }
+}
+
+impl Console for Plain {
+    /// There is nothing to suspend, outputs are simply appended.
+    fn suspend(&mut self) {}
+
+    fn writeln(&mut self, message: &str) {
+        writeln!(self.stdout, "{}", message).unwrap();
+    }
+
+    /// Outputs are written directly, pagers need a terminal.
+    fn page(&mut self, output: &[u8]) {
+        self.stdout.write_all(output).unwrap();
+    }
+
+    /// Outputs can be read again from the logs.
+    fn review(&mut self, _output: &[u8]) {}
+
+    fn output(&mut self) -> &mut dyn Write {
+        &mut self.stdout
+    }
+}
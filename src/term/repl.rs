@@ -1,26 +1,92 @@
+use std::env;
 use std::io::{BufWriter, Stdin, Stdout, Write};
+use std::process::{Command, Stdio};
 use termion::cursor::DetectCursorPos;
 use termion::event::Key;
-use termion::input::{Keys, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::{clear, color, cursor, style, terminal_size};
+use unicode_width::UnicodeWidthStr;
 
+use super::events::{Events, TermEvent};
+use super::line_editor::LineEditor;
 use super::theme::Theme;
+use super::{format_filters, pad, truncate, Console};
 use crate::data::SnapshotStatus;
 use crate::driver::View;
+use crate::parser;
+
+/// The pager used if `$PAGER` is not set.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// The minimum number of snapshots displayed by the REPL.
+const MIN_LIST_HEIGHT: u16 = 5;
+
+/// The number of lines of the REPL besides the snapshot list.
+const FRAME_HEIGHT: u16 = 8;
+
+/// The minimum width of the description box.
+const MIN_BOX_WIDTH: u16 = 20;
 
 pub enum Input {
     Up,
     Down,
     Quit,
+    /// The terminal has been resized, the view should be updated to the new
+    /// list height.
+    Resize,
+    /// Select the snapshot at the given index of the view.
+    Select(usize),
+    /// Toggle the mark of the selected snapshot.
+    Mark,
     Command(String),
 }
 
+/// A reverse incremental search through the history.
+struct Search {
+    query: String,
+    /// The index of the most recent history entry matching the query.
+    matched: Option<usize>,
+}
+
+impl Search {
+    /// Appends a character to the query, the current match is kept if it
+    /// still matches.
+    fn push(&mut self, c: char, history: &[String]) {
+        self.query.push(c);
+        let before = self.matched.map_or(history.len(), |pos| pos + 1);
+        self.matched = find_command(&history[..before], &self.query);
+    }
+
+    /// Removes the last character of the query, the most recent command
+    /// matching it is then matched.
+    fn pop(&mut self, history: &[String]) {
+        self.query.pop();
+        self.matched = find_command(history, &self.query);
+    }
+
+    /// Matches the previous command matching the query, if any.
+    fn older(&mut self, history: &[String]) {
+        let before = self.matched.unwrap_or(history.len());
+        if let Some(pos) = find_command(&history[..before], &self.query) {
+            self.matched = Some(pos);
+        }
+    }
+}
+
 pub struct Repl {
     /// Using raw mode stdout
     pub stdout: RawTerminal<BufWriter<Stdout>>,
-    stdin: Keys<Stdin>,
-    input: String,
+    events: Events,
+    input: LineEditor,
+    /// Previous commands, from the oldest to the most recent
+    history: Vec<String>,
+    /// The history entry being browsed, if any
+    history_pos: Option<usize>,
+    /// The input typed before browsing the history
+    draft: String,
+    search: Option<Search>,
+    /// Completion candidates displayed in place of the snapshot list
+    completions: Vec<String>,
     cursor_pos: (u16, u16),
     height: u16,
     theme: Theme,
@@ -31,28 +97,36 @@ pub struct Repl {
     waiting_symbol: String,
     failed_symbol: String,
     passed_symbol: String,
+    flaky_symbol: String,
+    marked_symbol: String,
 }
 
 impl Repl {
     /// Initialize the REPL internal state.
-    pub fn new(stdin: Stdin, stdout: Stdout) -> Repl {
+    pub fn new(stdin: Stdin, stdout: Stdout, history: Vec<String>) -> Repl {
         let mut stdout = BufWriter::new(stdout).into_raw_mode().unwrap();
-        let stdin = stdin.keys();
-        let input = String::from("");
+        let input = LineEditor::default();
         write!(stdout, "{}", cursor::Save).unwrap();
         let cursor_pos = stdout.cursor_pos().unwrap();
         let mut repl = Repl {
             stdout,
-            stdin,
+            events: Events::new(stdin),
             input,
+            history,
+            history_pos: None,
+            draft: String::new(),
+            search: None,
+            completions: Vec::new(),
             cursor_pos,
-            height: 8 + 5,
+            height: get_height(),
             suspended: false,
 
             // Symbols
             waiting_symbol: format!("{}~{}", color::Fg(color::LightBlue), color::Fg(color::Reset)),
             failed_symbol: format!("{}✗{}", color::Fg(color::LightRed), color::Fg(color::Reset)),
             passed_symbol: format!("{}✓{}", color::Fg(color::LightGreen), color::Fg(color::Reset)),
+            flaky_symbol: format!("{}≈{}", color::Fg(color::LightYellow), color::Fg(color::Reset)),
+            marked_symbol: format!("{}*{}", color::Fg(color::LightMagenta), color::Fg(color::Reset)),
 
             // Colors
             theme: Theme::new(),
@@ -66,15 +140,6 @@ impl Repl {
         write!(self.stdout, "{}{}", cursor::Restore, clear::AfterCursor).unwrap();
     }
 
-    /// Suspend REPL mode, stdout can be used normally until restored.
-    /// The repl should not be use while suspended.
-    pub fn suspend(&mut self) {
-        if !self.suspended {
-            self.clear();
-            self.suspended = true;
-        }
-    }
-
     /// Restore REPL mode, the repl can be re-started safely.
     fn restore(&mut self) {
         let (_, cursor_y) = self.stdout.cursor_pos().unwrap();
@@ -93,36 +158,124 @@ impl Repl {
         self.checkpoint();
     }
 
-    /// Writes a single line to the output. The REPL must have been suspended.
-    pub fn writeln(&mut self, message: &str) {
-        write!(self.stdout, "{}\r\n", message).unwrap();
+    /// Returns the number of snapshots that can be displayed at once.
+    pub fn list_height(&self) -> usize {
+        (self.height - FRAME_HEIGHT) as usize
+    }
+
+    /// Adapts the REPL to the new terminal size. The screen is cleared, as
+    /// terminals reflow their content in different ways.
+    fn resize(&mut self) {
+        self.height = get_height();
+        write!(self.stdout, "{}{}", clear::All, cursor::Goto(1, 1)).unwrap();
+        self.checkpoint();
+        self.suspended = false;
+    }
+
+    /// Sends an output to `$PAGER`, or `less -R` if it is not set.
+    /// Returns false if the pager could not be found.
+    fn run_pager(&mut self, output: &[u8]) -> bool {
+        let pager = match env::var("PAGER") {
+            Ok(pager) if !pager.trim().is_empty() => pager,
+            _ => String::from(DEFAULT_PAGER),
+        };
+        self.stdout.flush().unwrap();
+        self.stdout.suspend_raw_mode().unwrap();
+        let child = Command::new("sh").arg("-c").arg(&pager).stdin(Stdio::piped()).spawn();
+        let started = match child {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // The pager may exit before reading everything
+                    stdin.write_all(&normalize_line_breaks(output)).ok();
+                }
+                // The shell exits with 127 if the pager is not found
+                matches!(child.wait(), Ok(status) if status.code() != Some(127))
+            }
+            Err(_) => false,
+        };
+        self.stdout.activate_raw_mode().unwrap();
+        started
+    }
+
+    /// Returns true if an output can be displayed above the REPL.
+    fn fits(&self, output: &[u8]) -> bool {
+        let (_, term_height) = terminal_size().unwrap_or((80, 24));
+        let lines = output.iter().filter(|byte| **byte == b'\n').count();
+        lines + self.height as usize <= term_height as usize
     }
 
     /// Runs the REPL and returns control once a command has been received.
     pub fn run(&mut self, view: &View) -> Input {
         self.render(view);
         loop {
-            let key = match self.stdin.next() {
-                Some(key) => key.unwrap(),
+            let key = match self.events.next_event() {
+                Some(TermEvent::Key(key)) => key,
+                Some(TermEvent::Resize) => {
+                    self.resize();
+                    return Input::Resize;
+                }
                 None => return Input::Quit,
             };
+            let key = if self.search.is_some() {
+                match self.search_key(key) {
+                    Some(key) => key,
+                    None => {
+                        self.render(view);
+                        continue;
+                    }
+                }
+            } else {
+                key
+            };
+            self.completions.clear();
             match key {
                 Key::Down => return Input::Down,
                 Key::Up => return Input::Up,
                 Key::Esc => return Input::Quit,
-                Key::Delete | Key::Backspace => {
-                    self.input.pop();
-                    self.render(view);
-                }
-                Key::Char('\n') => {
-                    if self.input.len() > 0 {
-                        let mut command = String::new();
-                        std::mem::swap(&mut self.input, &mut command);
-                        return Input::Command(command);
+                Key::Char('\n') if !self.input.text.is_empty() => {
+                    let command = self.input.take();
+                    self.history_pos = None;
+                    if self.history.last() != Some(&command) {
+                        self.history.push(command.clone());
                     }
+                    return Input::Command(command);
+                }
+                Key::Char('\n') => (),
+                Key::Char('\t') => {
+                    self.complete(view);
+                    self.render(view);
                 }
+                // Commands never start with a space
+                Key::Char(' ') if self.input.text.is_empty() => return Input::Mark,
                 Key::Char(c) => {
-                    self.input.push(c);
+                    self.input.insert(c);
+                    self.history_pos = None;
+                    self.render(view);
+                }
+                Key::Backspace => self.edit(view, LineEditor::delete_before),
+                Key::Delete | Key::Ctrl('d') => self.edit(view, LineEditor::delete_after),
+                Key::Ctrl('w') => self.edit(view, LineEditor::delete_word_before),
+                Key::Ctrl('u') => self.edit(view, LineEditor::delete_to_start),
+                Key::Ctrl('k') => self.edit(view, LineEditor::delete_to_end),
+                Key::Left | Key::Ctrl('b') => self.edit(view, LineEditor::left),
+                Key::Right | Key::Ctrl('f') => self.edit(view, LineEditor::right),
+                Key::Alt('b') => self.edit(view, LineEditor::word_left),
+                Key::Alt('f') => self.edit(view, LineEditor::word_right),
+                Key::Home | Key::Ctrl('a') => self.edit(view, LineEditor::home),
+                Key::End | Key::Ctrl('e') => self.edit(view, LineEditor::end),
+                Key::Ctrl('p') => {
+                    self.history_previous();
+                    self.render(view);
+                }
+                Key::Ctrl('n') => {
+                    self.history_next();
+                    self.render(view);
+                }
+                Key::Ctrl('r') => {
+                    self.search = Some(Search {
+                        query: String::new(),
+                        matched: None,
+                    });
                     self.render(view);
                 }
                 Key::Ctrl('l') => {
@@ -137,6 +290,97 @@ impl Repl {
         }
     }
 
+    /// Applies an edition to the input, then renders the REPL.
+    fn edit(&mut self, view: &View, edition: fn(&mut LineEditor)) {
+        edition(&mut self.input);
+        self.history_pos = None;
+        self.render(view);
+    }
+
+    /// Completes the word before the cursor. If there are several candidates
+    /// their common prefix is inserted, they are listed if there is none.
+    fn complete(&mut self, view: &View) {
+        let before = &self.input.text[..self.input.cursor];
+        let (start, candidates) = parser::get_completions(before, &view.get_names(), &view.get_tags());
+        let word_len = self.input.cursor - start;
+        match candidates.len() {
+            0 => (),
+            1 => self.input.replace_before(start, &format!("{} ", candidates[0])),
+            _ => {
+                let prefix = common_prefix(&candidates);
+                if prefix.len() > word_len {
+                    self.input.replace_before(start, prefix);
+                } else {
+                    self.completions = candidates;
+                }
+            }
+        }
+        self.history_pos = None;
+    }
+
+    /// Replaces the input by the previous command of the history.
+    fn history_previous(&mut self) {
+        let pos = match self.history_pos {
+            Some(pos) => pos.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.input.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_pos = Some(pos);
+        self.input.set(self.history[pos].clone());
+    }
+
+    /// Replaces the input by the next command of the history, or by the
+    /// draft after the most recent command.
+    fn history_next(&mut self) {
+        match self.history_pos {
+            Some(pos) if pos + 1 < self.history.len() => {
+                self.history_pos = Some(pos + 1);
+                self.input.set(self.history[pos + 1].clone());
+            }
+            Some(_) => {
+                self.history_pos = None;
+                self.input.set(std::mem::take(&mut self.draft));
+            }
+            None => (),
+        }
+    }
+
+    /// Handles a key during a reverse search. Returns the key if it ends the
+    /// search and should be handled as usual, the matched command is then
+    /// kept as input.
+    fn search_key(&mut self, key: Key) -> Option<Key> {
+        let search = self.search.as_mut().unwrap();
+        match key {
+            Key::Char('\n') => (),
+            Key::Char(c) => {
+                search.push(c, &self.history);
+                return None;
+            }
+            Key::Delete | Key::Backspace => {
+                search.pop(&self.history);
+                return None;
+            }
+            Key::Ctrl('r') => {
+                search.older(&self.history);
+                return None;
+            }
+            Key::Esc | Key::Ctrl('g') => {
+                self.search = None;
+                return None;
+            }
+            _ => (),
+        }
+        if let Some(pos) = search.matched {
+            self.input.set(self.history[pos].clone());
+            self.history_pos = None;
+        }
+        self.search = None;
+        Some(key)
+    }
+
     /// Saves the cursor position, everything before the cursor will be
     /// preserved from any upcoming clear.
     fn checkpoint(&mut self) {
@@ -172,52 +416,102 @@ impl Repl {
         let bold = style::Bold;
         let clear_bold = style::Reset;
 
+        if !self.completions.is_empty() {
+            self.display_completions(view.height);
+            return;
+        }
+
+        let (width, _) = terminal_size().unwrap_or((80, 24));
+        let name_width = (width as usize).saturating_sub(5);
         let (min, max) = view.window;
         let data = view.get_view();
         for (pos, snap) in data[min..max].iter().enumerate() {
             let snap = snap.borrow();
+            let name = truncate(&snap.name, name_width);
             let status = match snap.status {
                 SnapshotStatus::Waiting => &self.waiting_symbol,
                 SnapshotStatus::Failed => &self.failed_symbol,
                 SnapshotStatus::Passed => &self.passed_symbol,
+                SnapshotStatus::Flaky => &self.flaky_symbol,
             };
+            let mark = if snap.marked { self.marked_symbol.as_str() } else { " " };
             if pos == view.cursor {
                 write!(
                     self.stdout,
-                    "{}{}{}>{} {} {}{}{}\r\n",
-                    bg, bold, cursor_color, clear_color, status, snap.name, clear_bold, clear_bg
+                    "{}{}{}>{}{}{} {}{}{}\r\n",
+                    bg, bold, cursor_color, clear_color, mark, status, name, clear_bold, clear_bg
                 )
                 .unwrap();
             } else {
-                write!(self.stdout, "{} {} {} {}\r\n", bg, clear_bg, status, snap.name).unwrap();
+                write!(self.stdout, "{} {}{}{} {}\r\n", bg, clear_bg, mark, status, name).unwrap();
             };
         }
         let current = if data.len() == 0 { 0 } else { min + view.cursor + 1 };
+        let marked = data.iter().filter(|snap| snap.borrow().marked).count();
+        let mut status = format!("  {}/{}", current, data.len());
+        if marked > 0 {
+            status.push_str(&format!(" ({} marked)", marked));
+        }
+        status.push_str(&format_filters(view));
         write!(
             self.stdout,
-            "  {}{}/{}{}",
+            "{}{}{}",
             color::Fg(color::White),
-            current,
-            data.len(),
+            truncate(&status, width as usize),
             color::Fg(color::Reset)
         )
         .unwrap();
     }
 
+    /// Displays the completion candidates over up to `height` lines.
+    fn display_completions(&mut self, height: usize) {
+        let (width, _) = terminal_size().unwrap_or((80, 24));
+        let width = width as usize;
+        let mut lines = vec![String::new()];
+        let mut shown = 0;
+        for candidate in &self.completions {
+            let line = lines.last_mut().unwrap();
+            if !line.is_empty() && line.width() + candidate.width() + 2 > width {
+                if lines.len() == height {
+                    break;
+                }
+                lines.push(String::new());
+            }
+            let line = lines.last_mut().unwrap();
+            line.push_str("  ");
+            line.push_str(candidate);
+            shown += 1;
+        }
+        for line in &lines {
+            write!(self.stdout, "{}\r\n", line).unwrap();
+        }
+        let hidden = self.completions.len() - shown;
+        if hidden > 0 {
+            write!(self.stdout, "  {}and {} more{}", color::Fg(color::White), hidden, color::Fg(color::Reset)).unwrap();
+        }
+    }
+
     /// Displays the input, return the offset of the input line.
     fn display_input(&mut self) -> u16 {
         let input_color = &self.theme.input;
         let clear_color = color::Fg(color::Reset);
         let bold = style::Bold;
         let clear_bold = style::Reset;
+        if let Some(search) = &self.search {
+            let history = &self.history;
+            let matched = search.matched.map_or("", |pos| &history[pos]);
+            let prompt = format!("(reverse-i-search)`{}'", search.query);
+            write!(self.stdout, "{}{}{}: {}\r\n", input_color, prompt, clear_color, matched).unwrap();
+            return prompt.chars().count() as u16 - 1;
+        }
         write!(
             self.stdout,
             "{}{}>{} {}{}",
-            bold, input_color, clear_color, self.input, clear_bold
+            bold, input_color, clear_color, self.input.text, clear_bold
         )
         .unwrap();
         write!(self.stdout, "\r\n").unwrap();
-        2 + self.input.chars().count() as u16
+        2 + self.input.cursor_width() as u16
     }
 
     fn display_description_box(&mut self, view: &View) {
@@ -233,7 +527,7 @@ impl Repl {
         let reset_color = color::Fg(color::Reset);
 
         // Compute sizes
-        let w = (w - 2) as usize; // remove 2 units for the box's borders
+        let w = (std::cmp::max(w, MIN_BOX_WIDTH) - 2) as usize; // remove 2 units for the box's borders
         let red_width = w / 3;
         let yellow_width = w / 6;
         let green_width = w / 9;
@@ -254,24 +548,24 @@ impl Repl {
                     descs.next().unwrap_or(""),
                     descs.next().unwrap_or(""),
                 );
-                let max_w = desc_width;
-                (
-                    &d1[..std::cmp::min(d1.len(), max_w)],
-                    &d2[..std::cmp::min(d2.len(), max_w)],
-                    &d3[..std::cmp::min(d3.len(), max_w)],
-                )
+                (d1, d2, d3)
             } else {
                 ("", "", "")
             };
-            let cmd = &snap.cmd[..std::cmp::min(snap.cmd.len(), cmd_width)];
-            let name = &snap.name[..std::cmp::min(snap.name.len(), name_width - 2)];
-            (name, cmd, desc)
+            let name = truncate(&snap.name, name_width - 2);
+            (name, snap.cmd.as_str(), desc)
         } else {
             ("", "", ("", "", ""))
         };
+        let cmd = pad(truncate(cmd, cmd_width), cmd_width);
+        let descs = (
+            pad(truncate(descs.0, desc_width), desc_width),
+            pad(truncate(descs.1, desc_width), desc_width),
+            pad(truncate(descs.2, desc_width), desc_width),
+        );
 
         // Build the top border
-        let n = name.len() + 2;
+        let n = name.width() + 2;
         let first_green_width;
         let mut first_yellow_width = yellow_width;
         let mut first_red_width = red_width;
@@ -321,10 +615,10 @@ impl Repl {
             self.stdout,
             "\
             {top_border}\r\n\
-            {b}│{rc} cmd: {bold}{cmd:<cmd_width$}{rs} {b}│{rc}\r\n\
-            {b}│{rc} {desc_1:<desc_width$} {b}│{rc}\r\n\
-            {b}│{rc} {desc_2:<desc_width$} {b}│{rc}\r\n\
-            {b}│{rc} {desc_3:<desc_width$} {b}│{rc}\r\n\
+            {b}│{rc} cmd: {bold}{cmd}{rs} {b}│{rc}\r\n\
+            {b}│{rc} {desc_1} {b}│{rc}\r\n\
+            {b}│{rc} {desc_2} {b}│{rc}\r\n\
+            {b}│{rc} {desc_3} {b}│{rc}\r\n\
             {b}└{x:─<bw$}{g}{x:─<gw$}{y}{x:─<yw$}{r}{x:─<rw$}{y}{x:─<yw$}{g}{x:─<gw$}{b}{x:─<bw$}┘{rc}\r\n\
             ",
             top_border = top_border,
@@ -341,12 +635,120 @@ impl Repl {
             yw = yellow_width,
             rw = red_width,
             cmd = cmd,
-            cmd_width = cmd_width,
             desc_1 = descs.0,
             desc_2 = descs.1,
             desc_3 = descs.2,
-            desc_width = desc_width,
         )
         .unwrap();
     }
 }
+
+impl Console for Repl {
+    /// Suspend REPL mode, stdout can be used normally until restored.
+    /// The repl should not be use while suspended.
+    fn suspend(&mut self) {
+        if !self.suspended {
+            self.clear();
+            self.suspended = true;
+        }
+    }
+
+    /// Writes a single line to the output. The REPL must have been suspended.
+    fn writeln(&mut self, message: &str) {
+        write!(self.stdout, "{}\r\n", message).unwrap();
+    }
+
+    /// Writes an output, outputs too long to fit above the REPL are displayed
+    /// through the pager. The REPL must have been suspended.
+    fn page(&mut self, output: &[u8]) {
+        if self.fits(output) || !self.run_pager(output) {
+            self.stdout.write_all(output).unwrap();
+        }
+    }
+
+    /// Outputs too long to fit above the REPL are displayed again through the
+    /// pager, so that they can be scrolled.
+    fn review(&mut self, output: &[u8]) {
+        if !self.fits(output) {
+            self.run_pager(output);
+        }
+    }
+
+    fn output(&mut self) -> &mut dyn Write {
+        &mut self.stdout
+    }
+}
+
+/// Replaces the `\n\r` and `\r\n` line breaks written in raw mode with `\n`,
+/// as pagers display carriage returns.
+fn normalize_line_breaks(output: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(output.len());
+    let mut idx = 0;
+    while idx < output.len() {
+        if output[idx..].starts_with(b"\n\r") || output[idx..].starts_with(b"\r\n") {
+            normalized.push(b'\n');
+            idx += 2;
+        } else {
+            normalized.push(output[idx]);
+            idx += 1;
+        }
+    }
+    normalized
+}
+
+/// Returns the index of the most recent command containing the query.
+fn find_command(history: &[String], query: &str) -> Option<usize> {
+    history.iter().rposition(|command| command.contains(query))
+}
+
+/// Returns the longest common prefix of a non-empty list of strings.
+fn common_prefix(strings: &[String]) -> &str {
+    let first = &strings[0];
+    let mut len = first.len();
+    for string in &strings[1..] {
+        len = first
+            .char_indices()
+            .zip(string.chars())
+            .take_while(|((idx, a), b)| *idx < len && a == b)
+            .last()
+            .map_or(0, |((idx, a), _)| idx + a.len_utf8());
+    }
+    &first[..len]
+}
+
+/// Returns the height of the REPL, the snapshot list grows with the terminal.
+fn get_height() -> u16 {
+    let (_, term_height) = terminal_size().unwrap_or((80, 24));
+    let list_height = term_height.saturating_sub(FRAME_HEIGHT) / 2;
+    FRAME_HEIGHT + std::cmp::max(list_height, MIN_LIST_HEIGHT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let history: Vec<String> = vec!["f #net", "r *", "f #net; r *", "s"].into_iter().map(String::from).collect();
+        let mut search = Search {
+            query: String::new(),
+            matched: None,
+        };
+        search.push('r', &history);
+        assert_eq!(search.matched, Some(2));
+        search.push(' ', &history);
+        assert_eq!(search.matched, Some(2));
+        // Ctrl-R goes to older commands, and stays on the oldest one
+        search.older(&history);
+        assert_eq!(search.matched, Some(1));
+        search.older(&history);
+        assert_eq!(search.matched, Some(1));
+        // The current match is kept while it matches
+        search.push('*', &history);
+        assert_eq!(search.matched, Some(1));
+        search.push('!', &history);
+        assert_eq!(search.matched, None);
+        search.pop(&history);
+        assert_eq!(search.matched, Some(2));
+    }
+}
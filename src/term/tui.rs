@@ -0,0 +1,478 @@
+use std::io::{BufWriter, Stdin, Stdout, Write};
+use termion::event::Key;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::{AlternateScreen, ToAlternateScreen};
+use termion::{clear, color, cursor, style, terminal_size};
+
+use super::events::{Events, TermEvent};
+use super::line_editor::LineEditor;
+use super::repl::Input;
+use super::theme::Theme;
+use super::{format_filters, format_status, pad, truncate, Console};
+use crate::ansi;
+use crate::data::{Snapshot, SnapshotStatus};
+use crate::driver::View;
+
+/// The bounds of the snapshot list width.
+const MIN_LIST_WIDTH: u16 = 16;
+const MAX_LIST_WIDTH: u16 = 40;
+
+/// The number of lines besides the snapshot list: the title and the command
+/// line.
+const FRAME_HEIGHT: u16 = 2;
+
+/// The number of columns a tab is expanded to in the detail pane.
+const TAB_WIDTH: usize = 4;
+
+const HINTS: &str = "↑↓ move  Space mark  / search  : command  Tab diff  J/K scroll  q quit";
+
+/// What the keys are currently editing.
+enum Mode {
+    Normal,
+    Command,
+    Search,
+}
+
+/// A full-screen interface, with the snapshot list on the left and the
+/// selected snapshot details on the right.
+pub struct Tui {
+    screen: AlternateScreen<RawTerminal<BufWriter<Stdout>>>,
+    events: Events,
+    mode: Mode,
+    command: LineEditor,
+    query: LineEditor,
+    /// The latest search, repeated with `n` and `N`
+    last_query: String,
+    /// The output of the latest commands, displayed in the detail pane
+    output: Vec<u8>,
+    /// The first line displayed in the detail pane
+    scroll: usize,
+    /// Show the stored outputs even if the latest run failed
+    show_stored: bool,
+    /// A message displayed on the command line
+    message: String,
+    /// If suspended, the output of previous commands is kept
+    suspended: bool,
+    theme: Theme,
+}
+
+impl Tui {
+    /// Switches to the alternate screen.
+    pub fn new(stdin: Stdin, stdout: Stdout) -> Tui {
+        let stdout = BufWriter::new(stdout).into_raw_mode().unwrap();
+        Tui {
+            screen: AlternateScreen::from(stdout),
+            events: Events::new(stdin),
+            mode: Mode::Normal,
+            command: LineEditor::default(),
+            query: LineEditor::default(),
+            last_query: String::new(),
+            output: Vec::new(),
+            scroll: 0,
+            show_stored: false,
+            message: String::new(),
+            suspended: false,
+            theme: Theme::new(),
+        }
+    }
+
+    /// Returns the number of snapshots that can be displayed at once.
+    pub fn list_height(&self) -> usize {
+        let (_, term_height) = terminal_size().unwrap_or((80, 24));
+        std::cmp::max(term_height.saturating_sub(FRAME_HEIGHT), 1) as usize
+    }
+
+    /// Runs the interface and returns control once an input has been received.
+    pub fn run(&mut self, view: &View) -> Input {
+        loop {
+            self.render(view);
+            let key = match self.events.next_event() {
+                Some(TermEvent::Key(key)) => key,
+                Some(TermEvent::Resize) => return Input::Resize,
+                None => return Input::Quit,
+            };
+            let input = match self.mode {
+                Mode::Normal => self.normal_key(key, view),
+                Mode::Command | Mode::Search => self.prompt_key(key, view),
+            };
+            if let Some(input) = input {
+                return input;
+            }
+        }
+    }
+
+    /// Handles a key while browsing snapshots.
+    fn normal_key(&mut self, key: Key, view: &View) -> Option<Input> {
+        self.message.clear();
+        let selected = view.get_selected_index();
+        let input = match key {
+            Key::Up | Key::Char('k') => Input::Up,
+            Key::Down | Key::Char('j') => Input::Down,
+            Key::PageUp => Input::Select(selected.saturating_sub(view.height)),
+            Key::PageDown => Input::Select(selected + view.height),
+            Key::Home | Key::Char('g') => Input::Select(0),
+            Key::End | Key::Char('G') => Input::Select(view.get_view().len().saturating_sub(1)),
+            Key::Char('n') => return self.find(view, &self.last_query.clone(), true),
+            Key::Char('N') => return self.find(view, &self.last_query.clone(), false),
+            Key::Char('J') => {
+                self.scroll += 1;
+                return None;
+            }
+            Key::Char('K') => {
+                self.scroll = self.scroll.saturating_sub(1);
+                return None;
+            }
+            Key::Char('\t') => {
+                self.show_stored = !self.show_stored;
+                self.scroll = 0;
+                return None;
+            }
+            Key::Char(' ') => return Some(Input::Mark),
+            Key::Char('/') => {
+                self.mode = Mode::Search;
+                return None;
+            }
+            Key::Char(':') => {
+                self.mode = Mode::Command;
+                return None;
+            }
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => return Some(Input::Quit),
+            _ => return None,
+        };
+        // The output of previous commands is about the previous selection
+        self.output.clear();
+        self.scroll = 0;
+        Some(input)
+    }
+
+    /// Handles a key while editing the command line or a search.
+    fn prompt_key(&mut self, key: Key, view: &View) -> Option<Input> {
+        let editor = match self.mode {
+            Mode::Search => &mut self.query,
+            _ => &mut self.command,
+        };
+        match key {
+            Key::Char('\n') => {
+                let text = editor.take();
+                match std::mem::replace(&mut self.mode, Mode::Normal) {
+                    Mode::Search => {
+                        self.last_query = text.clone();
+                        return self.find(view, &text, true);
+                    }
+                    _ if !text.trim().is_empty() => return Some(Input::Command(text)),
+                    _ => (),
+                }
+            }
+            Key::Esc | Key::Ctrl('c') => {
+                editor.take();
+                self.mode = Mode::Normal;
+            }
+            Key::Backspace if editor.text.is_empty() => self.mode = Mode::Normal,
+            Key::Char(c) => editor.insert(c),
+            Key::Backspace => editor.delete_before(),
+            Key::Delete | Key::Ctrl('d') => editor.delete_after(),
+            Key::Ctrl('w') => editor.delete_word_before(),
+            Key::Ctrl('u') => editor.delete_to_start(),
+            Key::Ctrl('k') => editor.delete_to_end(),
+            Key::Left | Key::Ctrl('b') => editor.left(),
+            Key::Right | Key::Ctrl('f') => editor.right(),
+            Key::Alt('b') => editor.word_left(),
+            Key::Alt('f') => editor.word_right(),
+            Key::Home | Key::Ctrl('a') => editor.home(),
+            Key::End | Key::Ctrl('e') => editor.end(),
+            _ => (),
+        }
+        None
+    }
+
+    /// Selects the next snapshot whose name contains the query, searching
+    /// backward if `forward` is false. The search wraps around the view.
+    fn find(&mut self, view: &View, query: &str, forward: bool) -> Option<Input> {
+        let snapshots = view.get_view();
+        let n = snapshots.len();
+        if n == 0 || query.is_empty() {
+            return None;
+        }
+        let selected = view.get_selected_index();
+        let found = (1..=n)
+            .map(|offset| {
+                if forward {
+                    (selected + offset) % n
+                } else {
+                    (selected + n - offset) % n
+                }
+            })
+            .find(|idx| snapshots[*idx].borrow().name.contains(query));
+        match found {
+            Some(idx) => {
+                self.output.clear();
+                self.scroll = 0;
+                Some(Input::Select(idx))
+            }
+            None => {
+                self.message = format!("Pattern not found: {}", query);
+                None
+            }
+        }
+    }
+
+    /// Displays the interface.
+    fn render(&mut self, view: &View) {
+        self.suspended = false;
+        let (width, height) = terminal_size().unwrap_or((80, 24));
+        let list_width = (width / 3).clamp(MIN_LIST_WIDTH, MAX_LIST_WIDTH);
+        let list_width = std::cmp::min(list_width, width.saturating_sub(2));
+        let detail_width = (width - list_width - 1) as usize;
+        let body_height = height.saturating_sub(FRAME_HEIGHT) as usize;
+        let (title, detail) = self.get_detail(view);
+        self.scroll = std::cmp::min(self.scroll, detail.len().saturating_sub(body_height));
+
+        // An editor may have left the alternate screen
+        write!(self.screen, "{}{}{}", ToAlternateScreen, clear::All, cursor::Hide).unwrap();
+
+        // Titles
+        let n = view.get_view().len();
+        let current = if n == 0 { 0 } else { view.get_selected_index() + 1 };
+        let marked = view.get_view().iter().filter(|snap| snap.borrow().marked).count();
+        let list_title = match marked {
+            0 => format!(" snapshots {}/{}", current, n),
+            _ => format!(" snapshots {}/{} ({} marked)", current, n, marked),
+        };
+        write!(
+            self.screen,
+            "{}{}{}{}{}│{} {}{}",
+            cursor::Goto(1, 1),
+            style::Bold,
+            pad(truncate(&list_title, list_width as usize), list_width as usize),
+            style::Reset,
+            self.theme.blue,
+            color::Fg(color::Reset),
+            truncate(&title, detail_width.saturating_sub(1)),
+            style::Reset,
+        )
+        .unwrap();
+
+        // Snapshot list and detail pane
+        let (min, max) = view.window;
+        let snapshots = &view.get_view()[min..max];
+        let name_width = (list_width as usize).saturating_sub(4);
+        for row in 0..body_height {
+            write!(self.screen, "{}", cursor::Goto(1, row as u16 + 2)).unwrap();
+            if let Some(snap) = snapshots.get(row) {
+                let snap = snap.borrow();
+                let name = pad(truncate(&snap.name, name_width), name_width);
+                let symbol = status_symbol(&snap.status);
+                let mark = match snap.marked {
+                    true => format!("{}*{}", color::Fg(color::LightMagenta), color::Fg(color::Reset)),
+                    false => String::from(" "),
+                };
+                if row == view.cursor {
+                    write!(
+                        self.screen,
+                        "{}{}{}>{}{}{} {}{}{}",
+                        color::Bg(color::Black),
+                        style::Bold,
+                        self.theme.cursor,
+                        color::Fg(color::Reset),
+                        mark,
+                        symbol,
+                        name,
+                        style::Reset,
+                        color::Bg(color::Reset)
+                    )
+                    .unwrap();
+                } else {
+                    write!(self.screen, " {}{} {}", mark, symbol, name).unwrap();
+                }
+            } else {
+                write!(self.screen, "{}", " ".repeat(list_width as usize)).unwrap();
+            }
+            write!(self.screen, "{}│{}", self.theme.blue, color::Fg(color::Reset)).unwrap();
+            if let Some(line) = detail.get(self.scroll + row) {
+                let line = ansi::truncate(line, detail_width.saturating_sub(1));
+                write!(self.screen, " {}", line).unwrap();
+            }
+        }
+
+        // Command line
+        write!(self.screen, "{}", cursor::Goto(1, height)).unwrap();
+        let prompt = match self.mode {
+            Mode::Normal => None,
+            Mode::Command => Some((':', &self.command)),
+            Mode::Search => Some(('/', &self.query)),
+        };
+        match prompt {
+            Some((symbol, editor)) => {
+                write!(
+                    self.screen,
+                    "{}{}{}{}{}",
+                    self.theme.input,
+                    symbol,
+                    color::Fg(color::Reset),
+                    truncate(&editor.text, (width as usize).saturating_sub(2)),
+                    cursor::Goto(2 + editor.cursor_width() as u16, height),
+                )
+                .unwrap();
+                write!(self.screen, "{}", cursor::Show).unwrap();
+            }
+            None if !self.message.is_empty() => {
+                write!(self.screen, "{}", truncate(&self.message, width as usize)).unwrap();
+            }
+            None if !view.get_filters().is_empty() => {
+                let filters = format!("filters: {}", format_filters(view).trim_start());
+                write!(self.screen, "{}", truncate(&filters, width as usize)).unwrap();
+            }
+            None => {
+                write!(
+                    self.screen,
+                    "{}{}{}",
+                    color::Fg(color::White),
+                    truncate(HINTS, width as usize),
+                    color::Fg(color::Reset)
+                )
+                .unwrap();
+            }
+        }
+        self.screen.flush().unwrap();
+    }
+
+    /// Returns the title and the lines of the detail pane: the output of the
+    /// latest commands if any, the selected snapshot otherwise.
+    fn get_detail(&self, view: &View) -> (String, Vec<String>) {
+        if !self.output.is_empty() {
+            return (String::from("output"), split_lines(&self.output));
+        }
+        let snap = match view.get_selected() {
+            Some(snap) => snap,
+            None => return (String::new(), vec![String::from("No snapshot")]),
+        };
+        let mut lines = self.get_header(&snap);
+        match &snap.diff {
+            Some(diff) if !self.show_stored => {
+                lines.extend(split_lines(diff));
+                (format!("{} · latest diff", snap.name), lines)
+            }
+            _ => {
+                for (stream, data) in &[("stdout", &snap.stdout), ("stderr", &snap.stderr)] {
+                    if let Some(data) = data {
+                        lines.push(format!(
+                            "{}── {} ──{}",
+                            self.theme.blue,
+                            stream,
+                            color::Fg(color::Reset)
+                        ));
+                        lines.extend(split_lines(&data.body));
+                    }
+                }
+                (format!("{} · stored output", snap.name), lines)
+            }
+        }
+    }
+
+    /// Returns the command, status, tags and description of a snapshot.
+    fn get_header(&self, snap: &Snapshot) -> Vec<String> {
+        let bold = style::Bold;
+        let reset = style::Reset;
+        let status = match snap.status {
+            SnapshotStatus::Waiting => "waiting",
+            SnapshotStatus::Passed => "passed",
+            SnapshotStatus::Failed => "failed",
+            SnapshotStatus::Flaky => "flaky",
+        };
+        let code = format_status(snap.exit_code, snap.signal, snap.core_dumped);
+        let mut lines = vec![
+            format!("cmd:    {}{}{}", bold, snap.cmd, reset),
+            format!("code:   {}{}{}", bold, code, reset),
+            format!("status: {} {}", status_symbol(&snap.status), status),
+        ];
+        if !snap.tags.is_empty() {
+            let tags: Vec<String> = snap.tags.iter().map(|tag| format!("#{}", tag)).collect();
+            lines.push(format!("tags:   {}", tags.join(" ")));
+        }
+        if let Some(description) = &snap.description {
+            lines.push(String::new());
+            lines.extend(description.lines().map(String::from));
+        }
+        lines.push(String::new());
+        lines
+    }
+}
+
+impl Console for Tui {
+    /// Clears the output of previous commands, the outputs of the upcoming
+    /// ones are displayed in the detail pane.
+    fn suspend(&mut self) {
+        if !self.suspended {
+            self.output.clear();
+            self.scroll = 0;
+            self.suspended = true;
+        }
+    }
+
+    fn writeln(&mut self, message: &str) {
+        write!(self.output, "{}\r\n", message).unwrap();
+    }
+
+    /// Outputs are displayed in the detail pane, where they can be scrolled.
+    fn page(&mut self, output: &[u8]) {
+        self.output.extend_from_slice(output);
+    }
+
+    /// Outputs already are in the detail pane.
+    fn review(&mut self, _output: &[u8]) {}
+
+    fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        write!(self.screen, "{}", cursor::Show).unwrap();
+        self.screen.flush().unwrap();
+    }
+}
+
+/// Returns the colored symbol of a snapshot status.
+fn status_symbol(status: &SnapshotStatus) -> String {
+    match status {
+        SnapshotStatus::Waiting => format!("{}~{}", color::Fg(color::LightBlue), color::Fg(color::Reset)),
+        SnapshotStatus::Failed => format!("{}✗{}", color::Fg(color::LightRed), color::Fg(color::Reset)),
+        SnapshotStatus::Passed => format!("{}✓{}", color::Fg(color::LightGreen), color::Fg(color::Reset)),
+        SnapshotStatus::Flaky => format!("{}≈{}", color::Fg(color::LightYellow), color::Fg(color::Reset)),
+    }
+}
+
+/// Splits an output into lines that can be displayed in a pane. Tabs are
+/// expanded, and control characters other than escapes are removed as they
+/// would move the cursor out of the pane.
+fn split_lines(output: &[u8]) -> Vec<String> {
+    let output = String::from_utf8_lossy(output);
+    output
+        .lines()
+        .map(|line| {
+            line.replace('\t', &" ".repeat(TAB_WIDTH))
+                .chars()
+                .filter(|c| !c.is_control() || *c == '\x1b')
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::term::BoxedWriter;
+
+    #[test]
+    fn test_split_lines() {
+        let theme = Theme::new();
+        let mut output = Vec::new();
+        output.boxed_write(b"a\tb\r\nc\x08d", &theme).unwrap();
+        let prefix = format!("{}│{} ", theme.blue, color::Fg(color::Reset));
+        assert_eq!(
+            split_lines(&output),
+            vec![format!("{}a    b", prefix), format!("{}cd", prefix), String::new()]
+        );
+    }
+}